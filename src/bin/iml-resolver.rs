@@ -0,0 +1,85 @@
+//! Local HTTP resolver of `did:iml` DIDs, serving `GET /1.0/identifiers/{did}`
+//!  in Universal Resolver driver shape.
+//!
+//! Usage: `iml-resolver [--listen <address>] <Iml file or directory>...`
+//!
+//! Files are CBOR serialized `Iml`s, each is verified before being served.
+
+use iml::{
+    resolver::{Resolver, DID_JSON_CONTENT_TYPE, DID_RESOLUTION_CONTENT_TYPE},
+    Iml,
+};
+use std::{error::Error, fs, path::Path};
+use tiny_http::{Header, Method, Response, Server};
+
+/// Address served by default, local only
+const DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut listen = DEFAULT_LISTEN.to_string();
+    let mut resolver = Resolver::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => listen = args.next().ok_or("--listen requires address")?,
+            path => load(&mut resolver, Path::new(path))?,
+        }
+    }
+    let server = Server::http(&listen).map_err(|e| format!("cannot listen on {listen}: {e}"))?;
+    eprintln!("iml-resolver listening on http://{listen}/1.0/identifiers/");
+    for request in server.incoming_requests() {
+        let result = match request.method() {
+            Method::Get => resolver.resolve_driver_path(request.url()),
+            _ => None,
+        };
+        let response = match result {
+            Some(result) => {
+                // plain DID Document, if that is all client accepts
+                let document_only = request.headers().iter().any(|header| {
+                    header.field.equiv("Accept")
+                        && header.value.as_str().contains(DID_JSON_CONTENT_TYPE)
+                });
+                let (body, content_type) = match (&result.did_document, document_only) {
+                    (Some(document), true) => (document.to_string(), DID_JSON_CONTENT_TYPE),
+                    _ => (
+                        result.to_driver_json().to_string(),
+                        DID_RESOLUTION_CONTENT_TYPE,
+                    ),
+                };
+                Response::from_string(body)
+                    .with_status_code(result.http_status())
+                    .with_header(header("Content-Type", content_type))
+            }
+            None => Response::from_string("Not Found").with_status_code(404),
+        };
+        if let Err(e) = request.respond(response) {
+            eprintln!("failed to respond: {e}");
+        }
+    }
+    Ok(())
+}
+
+/// Loads `Iml` file or all files of directory into `resolver`
+fn load(resolver: &mut Resolver, path: &Path) -> Result<(), Box<dyn Error>> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?.path();
+            if entry.is_file() {
+                load(resolver, &entry)?;
+            }
+        }
+        return Ok(());
+    }
+    let iml: Iml = serde_cbor::from_slice(&fs::read(path)?)
+        .map_err(|e| format!("{} is not an Iml: {e}", path.display()))?;
+    let did = iml.did();
+    resolver
+        .insert(iml)
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    eprintln!("loaded {did} from {}", path.display());
+    Ok(())
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field, value).expect("valid header")
+}
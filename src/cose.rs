@@ -0,0 +1,385 @@
+use crate::{
+    error::Error,
+    packer::{recipient_key, Unpacked},
+    replay::Freshness,
+    wallet::{key_id_generate, KeyId, KeyType, UnlockedWallet},
+    Attachment, Iml,
+};
+use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit, Nonce};
+use coset::{
+    cbor::Value, iana, AsCborValue, CborSerializable, CoseEncrypt, CoseEncrypt0,
+    CoseEncrypt0Builder, CoseEncryptBuilder, CoseKdfContextBuilder, CoseKey, CoseKeyBuilder,
+    CoseRecipientBuilder, CoseSign1, CoseSign1Builder, Header, HeaderBuilder, Label,
+    ProtectedHeader, RegisteredLabel, RegisteredLabelWithPrivate, SuppPubInfo,
+};
+use hkdf::Hkdf;
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+/// COSE `content type` of CBOR serialized [Iml] payloads
+pub const IML_CONTENT_TYPE: &str = "application/iml+cbor";
+
+/// COSE `content type` of CBOR serialized [Attachment] payloads
+pub const ATTACHMENT_CONTENT_TYPE: &str = "application/iml-attachment+cbor";
+
+/// AES-256-GCM nonce size
+const IV_SIZE: usize = 12;
+
+impl Iml {
+    /// Signs this state anew as COSE_Sign1 (RFC 9052): `as_verifiable` payload signed with `current_sk`
+    ///  over standard `Sig_structure`, with `kid` of [Iml::current_sk_key].
+    /// This is a fresh signature, not an encoding of `proof`: bare `proof` signs `as_verifiable` directly
+    ///  and can not be re-wrapped, so signing secret of this state must still be in the `wallet`.
+    /// Fails with [Error::KeyRetired] for states which signing secret was retired,
+    ///  e.g. superseded ones under [KeyRetention::RetireSuperseded](crate::wallet::KeyRetention::RetireSuperseded).
+    /// COSE representation of `proof` itself is out of scope, so such states have no COSE_Sign1.
+    pub fn sign_cose_sign1(&self, wallet: &UnlockedWallet) -> Result<Vec<u8>, Error> {
+        if self.is_discontinued() {
+            return Err(Error::Discontinued);
+        }
+        sign1(self.as_verifiable(), IML_CONTENT_TYPE, self, wallet)
+    }
+
+    /// Verifies COSE_Sign1 of self or any of it's previous states
+    ///  against `current_sk` of that state
+    pub fn verify_cose_sign1(&self, cose: &[u8]) -> bool {
+        let Ok(sign1) = CoseSign1::from_slice(cose) else {
+            return false;
+        };
+        self.history()
+            .iter()
+            .find(|state| sign1.payload.as_deref() == Some(state.as_verifiable().as_slice()))
+            .is_some_and(|state| verify_sign1(&sign1, state))
+    }
+
+    /// COSE_Encrypt (RFC 9052) of self for `their_did` with only selected attachments (or none):
+    ///  ECDH-ES + HKDF-256 direct key agreement with fresh ephemeral key and AES-256-GCM content encryption.
+    /// Recipient is referenced by `kid` of it's interaction key id, as in `did:iml` envelope.
+    /// Packed Iml is per-peer view stamped with signed [Freshness], as with [Iml::interact].
+    pub fn interact_cose(
+        &self,
+        wallet: &mut UnlockedWallet,
+        their_did: impl AsRef<str>,
+        attachments: Option<Vec<Attachment>>,
+    ) -> Result<Vec<u8>, Error> {
+        let (key_type, their_pk, peer) = recipient_key(their_did.as_ref(), wallet)?;
+        let pairwise_id = wallet.pairwise_key(&peer, self.get_interaction_key_type())?;
+        let mut to_pack = self.pairwise_view(wallet, &pairwise_id)?;
+        to_pack.attachments = attachments;
+        to_pack.freshness = Some(Freshness::new(&to_pack, wallet, None, &their_pk)?);
+        let (ephemeral, z) = key_type.ephemeral_agreement(&their_pk)?;
+        let recipient_protected = ProtectedHeader {
+            original_data: None,
+            header: HeaderBuilder::new()
+                .algorithm(iana::Algorithm::ECDH_ES_HKDF_256)
+                .build(),
+        };
+        let key = derive_key(&z, &recipient_protected)?;
+        let recipient = CoseRecipientBuilder::new()
+            .protected(recipient_protected.header)
+            .unprotected(
+                HeaderBuilder::new()
+                    .key_id(key_id_generate(&their_pk).to_vec())
+                    .value(
+                        iana::HeaderAlgorithmParameter::EphemeralKey as i64,
+                        cose_key(key_type, &ephemeral)?.to_cbor_value()?,
+                    )
+                    .build(),
+            )
+            .build();
+        let iv = random_iv();
+        let encrypt = CoseEncryptBuilder::new()
+            .protected(content_protected())
+            .unprotected(HeaderBuilder::new().iv(iv.clone()).build())
+            .add_recipient(recipient)
+            .try_create_ciphertext(&serde_cbor::to_vec(&to_pack)?, &[], |plaintext, aad| {
+                encrypt(&key, &iv, plaintext, aad)
+            })?
+            .build();
+        Ok(encrypt.to_vec()?)
+    }
+
+    /// Decrypts COSE_Encrypt made by [Iml::interact_cose] with interaction key of `kid`.
+    /// Use [Iml::from_cose_fresh] to accept it only once and only if it verifies.
+    pub fn from_cose(cose: &[u8], wallet: &UnlockedWallet) -> Result<Self, Error> {
+        Self::open_cose(cose, wallet).map(|unpacked| unpacked.iml)
+    }
+
+    /// Same as [Iml::from_cose], also returning our interaction key it was decrypted with
+    pub(crate) fn open_cose(cose: &[u8], wallet: &UnlockedWallet) -> Result<Unpacked, Error> {
+        let encrypt = CoseEncrypt::from_slice(cose)?;
+        let [recipient] = encrypt.recipients.as_slice() else {
+            return Err(Error::NotAnIml);
+        };
+        if !has_algorithm(
+            &recipient.protected.header,
+            iana::Algorithm::ECDH_ES_HKDF_256,
+        ) {
+            return Err(Error::UnsupportedKeyType);
+        }
+        let our_id: KeyId = recipient
+            .unprotected
+            .key_id
+            .as_slice()
+            .try_into()
+            .map_err(|_| Error::KeyNotFound)?;
+        let (key_type, ephemeral) = recipient
+            .unprotected
+            .rest
+            .iter()
+            .find(|(label, _)| {
+                *label == Label::Int(iana::HeaderAlgorithmParameter::EphemeralKey as i64)
+            })
+            .ok_or(Error::InvalidKeyEncoding)
+            .and_then(|(_, key)| {
+                public_key(CoseKey::from_cbor_value(key.clone()).map_err(Error::from)?)
+            })?;
+        let z = wallet.diffie_hellman_as(&our_id, &ephemeral, key_type)?;
+        let key = derive_key(&z, &recipient.protected)?;
+        let iv = content_iv(&encrypt.protected, &encrypt.unprotected)?;
+        let plaintext =
+            encrypt.decrypt(&[], |ciphertext, aad| decrypt(&key, &iv, ciphertext, aad))?;
+        Ok(Unpacked {
+            iml: serde_cbor::from_slice(&plaintext)?,
+            sender_key: None,
+            recipient_key: wallet.public_for(&our_id, key_type),
+        })
+    }
+
+    /// COSE_Encrypt0 (RFC 9052) of self with already shared 256 bit `key`, AES-256-GCM
+    pub fn to_cose_encrypt0(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        let iv = random_iv();
+        let encrypt0 = CoseEncrypt0Builder::new()
+            .protected(content_protected())
+            .unprotected(HeaderBuilder::new().iv(iv.clone()).build())
+            .try_create_ciphertext(&serde_cbor::to_vec(self)?, &[], |plaintext, aad| {
+                encrypt(key, &iv, plaintext, aad)
+            })?
+            .build();
+        Ok(encrypt0.to_vec()?)
+    }
+
+    /// Decrypts COSE_Encrypt0 made by [Iml::to_cose_encrypt0] with shared `key`
+    pub fn from_cose_encrypt0(cose: &[u8], key: &[u8]) -> Result<Self, Error> {
+        let encrypt0 = CoseEncrypt0::from_slice(cose)?;
+        let iv = content_iv(&encrypt0.protected, &encrypt0.unprotected)?;
+        let plaintext =
+            encrypt0.decrypt(&[], |ciphertext, aad| decrypt(key, &iv, ciphertext, aad))?;
+        Ok(serde_cbor::from_slice(&plaintext)?)
+    }
+}
+
+impl Attachment {
+    /// Signs this attachment anew as COSE_Sign1 (RFC 9052): `as_verifiable` payload signed
+    ///  with `current_sk` of `holder` over standard `Sig_structure`.
+    /// As with [Iml::sign_cose_sign1], signing secret of `holder` must still be in the `wallet`.
+    pub fn sign_cose_sign1(&self, holder: &Iml, wallet: &UnlockedWallet) -> Result<Vec<u8>, Error> {
+        if holder.is_discontinued() {
+            return Err(Error::Discontinued);
+        }
+        sign1(
+            self.as_verifiable(),
+            ATTACHMENT_CONTENT_TYPE,
+            holder,
+            wallet,
+        )
+    }
+
+    /// Verifies COSE_Sign1 of self against `current_sk` of `holder`
+    pub fn verify_cose_sign1(&self, cose: &[u8], holder: &Iml) -> bool {
+        CoseSign1::from_slice(cose).is_ok_and(|sign1| {
+            sign1.payload.as_deref() == Some(self.as_verifiable().as_slice())
+                && verify_sign1(&sign1, holder)
+        })
+    }
+}
+
+/// COSE_Sign1 of `payload` signed with `current_sk` of `signer`
+fn sign1(
+    payload: Vec<u8>,
+    content_type: &str,
+    signer: &Iml,
+    wallet: &UnlockedWallet,
+) -> Result<Vec<u8>, Error> {
+    let controller = signer.controller();
+    if wallet.is_retired(&controller) {
+        return Err(Error::KeyRetired);
+    }
+    let sk_type = signer.get_sk_type();
+    let protected = HeaderBuilder::new()
+        .algorithm(algorithm(sk_type)?)
+        .key_id(signer.current_sk_key().id.into_bytes())
+        .content_type(content_type.to_string())
+        .build();
+    let sign1 = CoseSign1Builder::new()
+        .protected(protected)
+        .payload(payload)
+        .try_create_signature(&[], |tbs| wallet.sign_as(tbs, &controller, sk_type))?
+        .build();
+    Ok(sign1.to_vec()?)
+}
+
+/// Verifies COSE_Sign1 against `current_sk` of `signer`
+fn verify_sign1(sign1: &CoseSign1, signer: &Iml) -> bool {
+    let header = &sign1.protected.header;
+    let sk_type = signer.get_sk_type();
+    algorithm(sk_type).is_ok_and(|alg| has_algorithm(header, alg))
+        && header.key_id == signer.current_sk_key().id.into_bytes()
+        && sign1
+            .verify_signature(&[], |signature, tbs| {
+                sk_type
+                    .verify(signer.get_current_sk(), tbs, signature)
+                    .then_some(())
+                    .ok_or(Error::VerificationFailed)
+            })
+            .is_ok()
+}
+
+/// COSE signature algorithm of signing key type
+fn algorithm(sk_type: KeyType) -> Result<iana::Algorithm, Error> {
+    match sk_type {
+        KeyType::Ed25519_256 => Ok(iana::Algorithm::ES256K),
+        KeyType::Ed25519 => Ok(iana::Algorithm::EdDSA),
+        KeyType::EcdsaP256 => Ok(iana::Algorithm::ES256),
+        KeyType::EcdhP256 | KeyType::X25519 => Err(Error::UnsupportedKeyType),
+    }
+}
+
+fn has_algorithm(header: &Header, alg: iana::Algorithm) -> bool {
+    header.alg == Some(RegisteredLabelWithPrivate::Assigned(alg))
+}
+
+/// Protected header of AES-256-GCM encrypted [Iml]
+fn content_protected() -> Header {
+    HeaderBuilder::new()
+        .algorithm(iana::Algorithm::A256GCM)
+        .content_type(IML_CONTENT_TYPE.to_string())
+        .build()
+}
+
+/// IV of AES-256-GCM encrypted content
+fn content_iv(protected: &ProtectedHeader, unprotected: &Header) -> Result<Vec<u8>, Error> {
+    if !has_algorithm(&protected.header, iana::Algorithm::A256GCM) {
+        return Err(Error::UnsupportedKeyType);
+    }
+    match unprotected.iv.len() {
+        IV_SIZE => Ok(unprotected.iv.clone()),
+        _ => Err(Error::NotAnIml),
+    }
+}
+
+fn random_iv() -> Vec<u8> {
+    let mut iv = vec![0u8; IV_SIZE];
+    OsRng.fill_bytes(&mut iv);
+    iv
+}
+
+fn encrypt(key: &[u8], iv: &[u8], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+    Aes256Gcm::new_from_slice(key)?
+        .encrypt(
+            Nonce::from_slice(iv),
+            aes_gcm::aead::Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| Error::ECDHCryptoError)
+}
+
+fn decrypt(key: &[u8], iv: &[u8], ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+    Aes256Gcm::new_from_slice(key)?
+        .decrypt(
+            Nonce::from_slice(iv),
+            aes_gcm::aead::Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| Error::ECDHCryptoError)
+}
+
+/// RFC 9053 HKDF-256 content key of direct key agreement,
+///  bound to recipient's `protected` header as received, if it was
+fn derive_key(z: &[u8], protected: &ProtectedHeader) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let context = CoseKdfContextBuilder::new()
+        .algorithm(iana::Algorithm::A256GCM)
+        .supp_pub_info(SuppPubInfo {
+            key_data_length: 256,
+            protected: protected.clone(),
+            other: None,
+        })
+        .build()
+        .to_vec()?;
+    let mut key = Zeroizing::new(vec![0u8; 32]);
+    Hkdf::<Sha256>::new(None, z)
+        .expand(&context, &mut key)
+        .map_err(|_| Error::ECDHCryptoError)?;
+    Ok(key)
+}
+
+/// COSE_Key of key agreement public key
+fn cose_key(key_type: KeyType, public: &[u8]) -> Result<CoseKey, Error> {
+    match key_type {
+        KeyType::EcdhP256 if public.len() == 65 => Ok(CoseKeyBuilder::new_ec2_pub_key(
+            iana::EllipticCurve::P_256,
+            public[1..33].to_vec(),
+            public[33..].to_vec(),
+        )
+        .build()),
+        KeyType::X25519 => Ok(CoseKeyBuilder::new_okp_key()
+            .param(
+                iana::OkpKeyParameter::Crv as i64,
+                Value::from(iana::EllipticCurve::X25519 as u64),
+            )
+            .param(
+                iana::OkpKeyParameter::X as i64,
+                Value::Bytes(public.to_vec()),
+            )
+            .build()),
+        KeyType::EcdhP256 => Err(Error::InvalidKeyEncoding),
+        _ => Err(Error::UnsupportedKeyType),
+    }
+}
+
+/// Key agreement public key of COSE_Key: SEC1 uncompressed for NIST P-256, raw for X25519
+fn public_key(key: CoseKey) -> Result<(KeyType, Vec<u8>), Error> {
+    let param = |label: i64| {
+        key.params
+            .iter()
+            .find(|(l, _)| *l == Label::Int(label))
+            .map(|(_, value)| value)
+    };
+    let bytes = |label: i64| {
+        param(label)
+            .and_then(Value::as_bytes)
+            .ok_or(Error::InvalidKeyEncoding)
+    };
+    let curve = param(iana::Ec2KeyParameter::Crv as i64)
+        .and_then(Value::as_integer)
+        .and_then(|crv| i64::try_from(crv).ok());
+    match (&key.kty, curve) {
+        (RegisteredLabel::Assigned(iana::KeyType::EC2), Some(crv))
+            if crv == iana::EllipticCurve::P_256 as i64 =>
+        {
+            let public = [
+                &[0x04],
+                bytes(iana::Ec2KeyParameter::X as i64)?.as_slice(),
+                bytes(iana::Ec2KeyParameter::Y as i64)?.as_slice(),
+            ]
+            .concat();
+            p256::PublicKey::from_sec1_bytes(&public)?;
+            Ok((KeyType::EcdhP256, public))
+        }
+        (RegisteredLabel::Assigned(iana::KeyType::OKP), Some(crv))
+            if crv == iana::EllipticCurve::X25519 as i64 =>
+        {
+            let public = bytes(iana::OkpKeyParameter::X as i64)?;
+            match public.len() {
+                32 => Ok((KeyType::X25519, public.clone())),
+                _ => Err(Error::InvalidKeyEncoding),
+            }
+        }
+        _ => Err(Error::UnsupportedKeyType),
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{error::Error, wallet::UnlockedWallet, Iml};
+use serde::{Deserialize, Serialize};
+
+/// Protocol label of data signature
+const DATA_SIGNATURE_LABEL: &[u8] = b"did:iml/data";
+
+/// Detached signature of arbitrary data by an identifier.
+/// Carries civilization of signer's `Iml`, so it stays verifiable after key evolutions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DataSignature {
+    /// Identifier of the signer
+    id: String,
+    /// Civilization of signer's `Iml` at signing
+    civilization: u64,
+    /// Signature with `current_sk` of that civilization
+    proof: Vec<u8>,
+}
+
+/// Content signed by [DataSignature::proof], bound to signer and civilization
+#[derive(Serialize)]
+struct DataSignatureContent<'a> {
+    id: &'a str,
+    civilization: u64,
+    /// Blake3 hash of signed data
+    digest: &'a [u8; 32],
+}
+
+impl DataSignature {
+    pub fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn get_civilization(&self) -> u64 {
+        self.civilization
+    }
+
+    pub fn get_proof(&self) -> &[u8] {
+        &self.proof
+    }
+
+    fn signed_content(id: &str, civilization: u64, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut content = DATA_SIGNATURE_LABEL.to_vec();
+        content.extend(serde_cbor::to_vec(&DataSignatureContent {
+            id,
+            civilization,
+            digest: blake3::hash(data).as_bytes(),
+        })?);
+        Ok(content)
+    }
+}
+
+impl Iml {
+    /// Signs `data` as this identifier with `current_sk` of current civilization
+    pub fn sign_data(
+        &self,
+        wallet: &UnlockedWallet,
+        data: impl AsRef<[u8]>,
+    ) -> Result<DataSignature, Error> {
+        if self.is_discontinued() {
+            return Err(Error::Discontinued);
+        }
+        let content = DataSignature::signed_content(&self.id, self.civilization, data.as_ref())?;
+        Ok(DataSignature {
+            id: self.id.clone(),
+            civilization: self.civilization,
+            proof: wallet.sign_as(content, &self.controller(), self.get_sk_type())?,
+        })
+    }
+
+    /// Verifies `signature` of `data` against `current_sk` of self at signature's civilization.
+    /// Signatures of civilizations newer than self or of discontinued state are rejected.
+    /// Self should be [Iml::verify]ed beforehand.
+    pub fn verify_data(&self, data: impl AsRef<[u8]>, signature: &DataSignature) -> bool {
+        if signature.id != self.id {
+            return false;
+        }
+        let Some(state) = self.at_civilization(signature.civilization) else {
+            return false;
+        };
+        !state.is_discontinued()
+            && DataSignature::signed_content(&self.id, signature.civilization, data.as_ref())
+                .is_ok_and(|content| {
+                    state
+                        .get_sk_type()
+                        .verify(state.get_current_sk(), &content, &signature.proof)
+                })
+    }
+}
@@ -0,0 +1,33 @@
+use crate::{error::Error, key_formats::agreement_key_from_multikey, wallet::KeyType};
+
+/// DID method prefix of `did:key` identifiers
+const DID_KEY_PREFIX: &str = "did:key:";
+
+/// DID method prefix of `did:peer` identifiers
+const DID_PEER_PREFIX: &str = "did:peer:";
+
+/// `did:peer:2` purpose code of key agreement keys
+const PURPOSE_KEY_AGREEMENT: char = 'E';
+
+/// Key agreement key of `did:key`, `did:peer:0` or `did:peer:2` DID.
+/// `None` for DIDs of other methods, first `E` key is used of `did:peer:2`.
+pub(crate) fn key_agreement_of(did: &str) -> Result<Option<(KeyType, Vec<u8>)>, Error> {
+    let multikey = if let Some(multikey) = did.strip_prefix(DID_KEY_PREFIX) {
+        multikey
+    } else if let Some(peer) = did.strip_prefix(DID_PEER_PREFIX) {
+        match peer.split_at_checked(1) {
+            Some(("0", multikey)) => multikey,
+            Some(("2", elements)) => elements
+                .split('.')
+                .filter_map(|element| element.strip_prefix(PURPOSE_KEY_AGREEMENT))
+                .next()
+                .ok_or(Error::KeyNotFound)?,
+            _ => return Err(Error::NotADid),
+        }
+    } else {
+        return Ok(None);
+    };
+    // DID URL parts are not part of the key
+    let multikey = multikey.split(['#', '?', '/']).next().unwrap_or_default();
+    agreement_key_from_multikey(multikey).map(Some)
+}
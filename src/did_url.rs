@@ -0,0 +1,146 @@
+use crate::{document::DID_IML_PREFIX, error::Error};
+use std::{fmt, str::FromStr};
+
+/// Query parameter selecting historical civilization
+pub const VERSION_ID: &str = "versionId";
+
+/// Query parameter selecting attachment by it's `payload_type`
+pub const PAYLOAD_TYPE: &str = "payloadType";
+
+/// Parsed `did:iml:<id>[/path][?query][#fragment]` DID URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DidUrl {
+    id: String,
+    path: String,
+    query: Vec<(String, String)>,
+    fragment: Option<String>,
+}
+
+impl DidUrl {
+    /// Identifier part
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// DID without path, query and fragment
+    pub fn did(&self) -> String {
+        format!("{DID_IML_PREFIX}{}", self.id)
+    }
+
+    /// Path, including leading `/`, or empty
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Percent decoded value of query parameter
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn fragment(&self) -> Option<&str> {
+        self.fragment.as_deref()
+    }
+
+    /// Civilization selected with `versionId`
+    pub fn version_id(&self) -> Result<Option<u64>, Error> {
+        self.query(VERSION_ID)
+            .map(|version| version.parse().map_err(|_| Error::NotADid))
+            .transpose()
+    }
+
+    /// Attachment `payload_type` selected with `payloadType`
+    pub fn payload_type(&self) -> Option<&str> {
+        self.query(PAYLOAD_TYPE)
+    }
+}
+
+impl FromStr for DidUrl {
+    type Err = Error;
+
+    fn from_str(url: &str) -> Result<Self, Self::Err> {
+        let (url, fragment) = match url.split_once('#') {
+            Some((url, fragment)) => (url, Some(percent_decode(fragment)?)),
+            None => (url, None),
+        };
+        let (url, query) = url.split_once('?').unwrap_or((url, ""));
+        let (did, path) = match url.find('/') {
+            Some(at) => url.split_at(at),
+            None => (url, ""),
+        };
+        if !did.starts_with("did:") {
+            return Err(Error::NotADid);
+        }
+        // interaction `did:iml`s can not be dereferenced
+        let id = did.strip_prefix(DID_IML_PREFIX).ok_or(Error::NotAnIml)?;
+        if id.is_empty() || hex::decode(id).is_err() {
+            return Err(Error::NotAnIml);
+        }
+        let query = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                Ok((percent_decode(key)?, percent_decode(value)?))
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Self {
+            id: id.to_string(),
+            path: path.to_string(),
+            query,
+            fragment,
+        })
+    }
+}
+
+impl fmt::Display for DidUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.did(), self.path)?;
+        for (i, (key, value)) in self.query.iter().enumerate() {
+            let separator = if i == 0 { '?' } else { '&' };
+            write!(
+                f,
+                "{separator}{}={}",
+                percent_encode(key),
+                percent_encode(value)
+            )?;
+        }
+        if let Some(fragment) = &self.fragment {
+            write!(f, "#{}", percent_encode(fragment))?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes `%XX` escapes of URL part
+pub(crate) fn percent_decode(encoded: &str) -> Result<String, Error> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.bytes();
+    while let Some(byte) = bytes.next() {
+        decoded.push(match byte {
+            b'%' => {
+                let hex = [
+                    bytes.next().ok_or(Error::NotADid)?,
+                    bytes.next().ok_or(Error::NotADid)?,
+                ];
+                hex::decode(hex).map_err(|_| Error::NotADid)?[0]
+            }
+            byte => byte,
+        });
+    }
+    String::from_utf8(decoded).map_err(|_| Error::NotADid)
+}
+
+fn percent_encode(decoded: &str) -> String {
+    decoded
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b':' | b'/' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
@@ -0,0 +1,448 @@
+use crate::{
+    document::DID_IML_PREFIX,
+    error::Error,
+    key_formats::{agreement_key_from_jwk, public_jwk, VerificationKey},
+    resolver::Resolver,
+    wallet::{key_id_generate, UnlockedWallet},
+    Iml,
+};
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Nonce,
+};
+use aes_kw::KekAes256;
+use base64ct::{Base64UrlUnpadded, Encoding};
+use cbc::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256, Sha512};
+use zeroize::Zeroizing;
+
+/// Media type of DIDComm v2 encrypted message
+pub const DIDCOMM_ENCRYPTED: &str = "application/didcomm-encrypted+json";
+
+/// Key wrapping algorithm of authcrypt
+const AUTHCRYPT_ALG: &str = "ECDH-1PU+A256KW";
+/// Content encryption of authcrypt, ECDH-1PU requires it to be authenticated by the sender
+const AUTHCRYPT_ENC: &str = "A256CBC-HS512";
+/// Key wrapping algorithm of anoncrypt
+const ANONCRYPT_ALG: &str = "ECDH-ES+A256KW";
+/// Content encryption of anoncrypt
+const ANONCRYPT_ENC: &str = "A256GCM";
+/// A256CBC-HS512 tag, left half of HMAC-SHA-512 output
+const AUTHCRYPT_TAG_SIZE: usize = 32;
+/// A256GCM tag
+const ANONCRYPT_TAG_SIZE: usize = 16;
+
+/// RFC 7516 JWE in General JSON Serialization
+#[derive(Serialize, Deserialize)]
+struct Jwe {
+    protected: String,
+    recipients: Vec<JweRecipient>,
+    iv: String,
+    ciphertext: String,
+    tag: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JweRecipient {
+    header: RecipientHeader,
+    encrypted_key: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecipientHeader {
+    kid: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProtectedHeader {
+    typ: String,
+    alg: String,
+    enc: String,
+    epk: Value,
+    apv: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    skid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    apu: Option<String>,
+}
+
+/// Unpacked DIDComm v2 message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unpacked {
+    /// Plaintext message
+    pub message: Value,
+    /// Our key the message was decrypted with
+    pub recipient_kid: String,
+    /// Sender's interaction key, authenticated by authcrypt.
+    /// `None` for anoncrypt.
+    pub sender_kid: Option<String>,
+}
+
+impl Iml {
+    /// Packs DIDComm v2 plaintext `message` with authcrypt (ECDH-1PU+A256KW, A256CBC-HS512)
+    ///  from our interaction key to interaction keys of `to` peers.
+    /// Pairwise key dedicated to the peers by [Iml::interact] is used, as they know us by it.
+    /// Fails with `KeyNotFound` if peers know us by different keys - pack for each of them then.
+    pub fn pack_didcomm(
+        &self,
+        wallet: &UnlockedWallet,
+        message: &Value,
+        to: &[&Iml],
+    ) -> Result<String, Error> {
+        let key_type = self.get_interaction_key_type();
+        let mut senders = to.iter().map(|peer| {
+            wallet
+                .pairwise_key_for(&peer.get_id(), key_type)
+                .and_then(|id| wallet.public_for(&id, key_type))
+                .unwrap_or_else(|| self.active_interaction_key())
+        });
+        let sender = senders.next().ok_or(Error::KeyNotFound)?;
+        if senders.any(|other| other != sender) {
+            return Err(Error::KeyNotFound);
+        }
+        pack(message, to, Some((wallet, self.interaction_key_of(sender))))
+    }
+
+    /// Packs DIDComm v2 plaintext `message` with anoncrypt (ECDH-ES+A256KW, A256GCM)
+    ///  to interaction keys of `to` peers.
+    pub fn pack_didcomm_anon(message: &Value, to: &[&Iml]) -> Result<String, Error> {
+        pack(message, to, None)
+    }
+
+    /// Unpacks DIDComm v2 encrypted message addressed to any of our interaction keys, pairwise ones included.
+    /// Authcrypt sender's key is resolved from `resolver` and must match message's `from`.
+    pub fn unpack_didcomm(
+        &self,
+        wallet: &UnlockedWallet,
+        jwe: impl AsRef<str>,
+        resolver: &Resolver,
+    ) -> Result<Unpacked, Error> {
+        let jwe: Jwe = serde_json::from_str(jwe.as_ref()).map_err(|_| Error::MalformedMessage)?;
+        let header: ProtectedHeader = serde_json::from_slice(&decode(&jwe.protected)?)
+            .map_err(|_| Error::MalformedMessage)?;
+        let authcrypt = match (header.alg.as_str(), header.enc.as_str()) {
+            (AUTHCRYPT_ALG, AUTHCRYPT_ENC) => true,
+            (ANONCRYPT_ALG, ANONCRYPT_ENC) => false,
+            _ => return Err(Error::UnsupportedKeyType),
+        };
+        let kids = jwe.recipients.iter().map(|r| r.header.kid.as_str());
+        if header.apv != apv(kids) {
+            return Err(Error::MalformedMessage);
+        }
+        let (key_type, epk) = agreement_key_from_jwk(&header.epk)?;
+        let sender = match (authcrypt, &header.skid) {
+            (false, _) => None,
+            (true, Some(skid)) if header.apu.as_deref() == Some(&encode(skid)) => {
+                Some(resolve_key(resolver, skid)?)
+            }
+            (true, _) => return Err(Error::MalformedMessage),
+        };
+        if sender
+            .as_ref()
+            .is_some_and(|sender| sender.key_type != key_type)
+        {
+            return Err(Error::UnsupportedKeyType);
+        }
+        let tag = decode(&jwe.tag)?;
+        let tag_size = if authcrypt {
+            AUTHCRYPT_TAG_SIZE
+        } else {
+            ANONCRYPT_TAG_SIZE
+        };
+        if tag.len() != tag_size {
+            return Err(Error::MalformedMessage);
+        }
+        // peers which learned us by interaction address our pairwise keys
+        let ours: Vec<_> = wallet
+            .pairwise_keys()
+            .iter()
+            .filter(|id| wallet.key_type(id) == Some(key_type))
+            .filter_map(|id| wallet.public_for(id, key_type))
+            .map(|public| self.interaction_key_of(public))
+            .chain(self.history().iter().map(Iml::interaction_verification_key))
+            .collect();
+        for recipient in &jwe.recipients {
+            let Some(our) = ours
+                .iter()
+                .find(|our| our.id == recipient.header.kid && our.key_type == key_type)
+            else {
+                continue;
+            };
+            let our_id = key_id_generate(&our.public);
+            // superseded key may be past it's grace period already
+            let Ok(mut z) = wallet.diffie_hellman_as(&our_id, &epk, key_type) else {
+                continue;
+            };
+            if let Some(sender) = &sender {
+                z.extend_from_slice(&wallet.diffie_hellman_as(
+                    &our_id,
+                    &sender.public,
+                    key_type,
+                )?);
+            }
+            let kek = kdf(&z, &header, authcrypt.then_some(tag.as_slice()))?;
+            let cek = Zeroizing::new(
+                KekAes256::try_from(kek.as_slice())
+                    .map_err(|_| Error::ECDHCryptoError)?
+                    .unwrap_vec(&decode(&recipient.encrypted_key)?)
+                    .map_err(|_| Error::MalformedMessage)?,
+            );
+            let plaintext = decrypt_content(
+                &header.enc,
+                &cek,
+                jwe.protected.as_bytes(),
+                &decode(&jwe.iv)?,
+                &decode(&jwe.ciphertext)?,
+                &tag,
+            )?;
+            let message: Value =
+                serde_json::from_slice(&plaintext).map_err(|_| Error::MalformedMessage)?;
+            if let Some(sender) = &sender {
+                // sender is authenticated only as `from` of the message
+                let from = message["from"].as_str().ok_or(Error::MalformedMessage)?;
+                if sender.id.split('#').next() != Some(from) {
+                    return Err(Error::MalformedMessage);
+                }
+            }
+            return Ok(Unpacked {
+                message,
+                recipient_kid: our.id.clone(),
+                sender_kid: header.skid,
+            });
+        }
+        Err(Error::KeyNotFound)
+    }
+}
+
+fn pack(
+    message: &Value,
+    to: &[&Iml],
+    sender: Option<(&UnlockedWallet, VerificationKey)>,
+) -> Result<String, Error> {
+    let recipients: Vec<_> = to
+        .iter()
+        .map(|iml| iml.interaction_verification_key())
+        .collect();
+    let key_type = recipients.first().ok_or(Error::KeyNotFound)?.key_type;
+    if recipients.iter().any(|r| r.key_type != key_type)
+        || sender.as_ref().is_some_and(|(_, s)| s.key_type != key_type)
+    {
+        return Err(Error::UnsupportedKeyType);
+    }
+    let ephemeral = key_type.generate();
+    let epk = key_type
+        .public_from(&ephemeral)
+        .ok_or(Error::ECDHCryptoError)?;
+    let (alg, enc, cek_size) = match sender {
+        Some(_) => (AUTHCRYPT_ALG, AUTHCRYPT_ENC, 64),
+        None => (ANONCRYPT_ALG, ANONCRYPT_ENC, 32),
+    };
+    let header = ProtectedHeader {
+        typ: DIDCOMM_ENCRYPTED.to_string(),
+        alg: alg.to_string(),
+        enc: enc.to_string(),
+        epk: public_jwk(key_type, &epk)?,
+        apv: apv(recipients.iter().map(|r| r.id.as_str())),
+        skid: sender.as_ref().map(|(_, s)| s.id.clone()),
+        apu: sender.as_ref().map(|(_, s)| encode(&s.id)),
+    };
+    let protected = encode(serde_json::to_vec(&header).map_err(|_| Error::MalformedMessage)?);
+    let mut cek = Zeroizing::new(vec![0u8; cek_size]);
+    OsRng.fill_bytes(&mut cek);
+    let plaintext = serde_json::to_vec(message).map_err(|_| Error::MalformedMessage)?;
+    let (iv, ciphertext, tag) = encrypt_content(enc, &cek, protected.as_bytes(), &plaintext)?;
+    let recipients = recipients
+        .iter()
+        .map(|recipient| {
+            let mut z = key_type.agree(&ephemeral, &recipient.public)?;
+            if let Some((wallet, sender)) = &sender {
+                z.extend_from_slice(&wallet.diffie_hellman_as(
+                    &key_id_generate(&sender.public),
+                    &recipient.public,
+                    key_type,
+                )?);
+            }
+            let kek = kdf(&z, &header, sender.is_some().then_some(tag.as_slice()))?;
+            Ok(JweRecipient {
+                header: RecipientHeader {
+                    kid: recipient.id.clone(),
+                },
+                encrypted_key: encode(
+                    KekAes256::try_from(kek.as_slice())
+                        .map_err(|_| Error::ECDHCryptoError)?
+                        .wrap_vec(&cek)
+                        .map_err(|_| Error::ECDHCryptoError)?,
+                ),
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+    serde_json::to_string(&Jwe {
+        protected,
+        recipients,
+        iv: encode(iv),
+        ciphertext: encode(ciphertext),
+        tag: encode(tag),
+    })
+    .map_err(|_| Error::MalformedMessage)
+}
+
+/// Interaction key of peer known to `resolver` by it's absolute `kid`
+fn resolve_key(resolver: &Resolver, kid: &str) -> Result<VerificationKey, Error> {
+    let id = kid
+        .split('#')
+        .next()
+        .and_then(|did| did.strip_prefix(DID_IML_PREFIX))
+        .ok_or(Error::NotAnIml)?;
+    resolver
+        .get(id)
+        .ok_or(Error::KeyNotFound)?
+        .history()
+        .iter()
+        .map(Iml::interaction_verification_key)
+        .find(|key| key.id == kid)
+        .ok_or(Error::KeyNotFound)
+}
+
+/// Agreement PartyVInfo - hash of sorted recipient key ids
+fn apv<'a>(kids: impl Iterator<Item = &'a str>) -> String {
+    let mut kids: Vec<_> = kids.collect();
+    kids.sort_unstable();
+    encode(Sha256::digest(kids.join(".").as_bytes()))
+}
+
+/// NIST SP 800-56A Concat KDF of 256 bit key wrapping key.
+/// ECDH-1PU binds content encryption `tag` as well.
+fn kdf(
+    z: &[u8],
+    header: &ProtectedHeader,
+    tag: Option<&[u8]>,
+) -> Result<Zeroizing<[u8; 32]>, Error> {
+    let apu = header.apu.as_deref().map(decode).transpose()?;
+    let apv = decode(&header.apv)?;
+    let mut hasher = Sha256::new();
+    hasher.update(1u32.to_be_bytes());
+    hasher.update(z);
+    for info in [
+        header.alg.as_bytes(),
+        apu.as_deref().unwrap_or_default(),
+        &apv,
+    ] {
+        hasher.update((info.len() as u32).to_be_bytes());
+        hasher.update(info);
+    }
+    hasher.update(256u32.to_be_bytes());
+    if let Some(tag) = tag {
+        hasher.update((tag.len() as u32).to_be_bytes());
+        hasher.update(tag);
+    }
+    Ok(Zeroizing::new(hasher.finalize().into()))
+}
+
+/// Initialization vector, ciphertext and authentication tag of encrypted content
+type Encrypted = (Vec<u8>, Vec<u8>, Vec<u8>);
+
+/// Encrypts content, returning iv, ciphertext and tag
+fn encrypt_content(
+    enc: &str,
+    cek: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<Encrypted, Error> {
+    match enc {
+        ANONCRYPT_ENC => {
+            let mut iv = vec![0u8; 12];
+            OsRng.fill_bytes(&mut iv);
+            let mut ciphertext = Aes256Gcm::new_from_slice(cek)?
+                .encrypt(
+                    Nonce::from_slice(&iv),
+                    Payload {
+                        msg: plaintext,
+                        aad,
+                    },
+                )
+                .map_err(|_| Error::ECDHCryptoError)?;
+            let tag = ciphertext.split_off(ciphertext.len() - ANONCRYPT_TAG_SIZE);
+            Ok((iv, ciphertext, tag))
+        }
+        AUTHCRYPT_ENC => {
+            let (mac_key, enc_key) = cek.split_at(32);
+            let mut iv = vec![0u8; 16];
+            OsRng.fill_bytes(&mut iv);
+            let ciphertext = cbc::Encryptor::<aes::Aes256>::new_from_slices(enc_key, &iv)?
+                .encrypt_padded_vec_mut::<Pkcs7>(plaintext);
+            let tag = cbc_hmac(mac_key, aad, &iv, &ciphertext)?
+                .finalize()
+                .into_bytes()[..AUTHCRYPT_TAG_SIZE]
+                .to_vec();
+            Ok((iv, ciphertext, tag))
+        }
+        _ => Err(Error::UnsupportedKeyType),
+    }
+}
+
+fn decrypt_content(
+    enc: &str,
+    cek: &[u8],
+    aad: &[u8],
+    iv: &[u8],
+    ciphertext: &[u8],
+    tag: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, Error> {
+    match enc {
+        ANONCRYPT_ENC if iv.len() == 12 && tag.len() == ANONCRYPT_TAG_SIZE => {
+            Aes256Gcm::new_from_slice(cek)?
+                .decrypt(
+                    Nonce::from_slice(iv),
+                    Payload {
+                        msg: &[ciphertext, tag].concat(),
+                        aad,
+                    },
+                )
+                .map(Zeroizing::new)
+                .map_err(|_| Error::MalformedMessage)
+        }
+        AUTHCRYPT_ENC if cek.len() == 64 => {
+            // truncated tag would be accepted by left comparison alone.
+            // `verify_slice` expects full output, so left half of checked length is compared
+            if tag.len() != AUTHCRYPT_TAG_SIZE {
+                return Err(Error::MalformedMessage);
+            }
+            let (mac_key, enc_key) = cek.split_at(32);
+            cbc_hmac(mac_key, aad, iv, ciphertext)?
+                .verify_truncated_left(tag)
+                .map_err(|_| Error::MalformedMessage)?;
+            cbc::Decryptor::<aes::Aes256>::new_from_slices(enc_key, iv)?
+                .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+                .map(Zeroizing::new)
+                .map_err(|_| Error::MalformedMessage)
+        }
+        _ => Err(Error::MalformedMessage),
+    }
+}
+
+/// RFC 7518 AES_CBC_HMAC_SHA2 authentication
+fn cbc_hmac(
+    mac_key: &[u8],
+    aad: &[u8],
+    iv: &[u8],
+    ciphertext: &[u8],
+) -> Result<Hmac<Sha512>, Error> {
+    let mut mac = <Hmac<Sha512> as Mac>::new_from_slice(mac_key)?;
+    mac.update(aad);
+    mac.update(iv);
+    mac.update(ciphertext);
+    mac.update(&((aad.len() as u64) * 8).to_be_bytes());
+    Ok(mac)
+}
+
+fn encode(data: impl AsRef<[u8]>) -> String {
+    Base64UrlUnpadded::encode_string(data.as_ref())
+}
+
+fn decode(data: &str) -> Result<Vec<u8>, Error> {
+    Base64UrlUnpadded::decode_vec(data).map_err(|_| Error::MalformedMessage)
+}
@@ -0,0 +1,95 @@
+use crate::{
+    error::Error,
+    service::{ServiceChange, SERVICE_PAYLOAD_TYPE},
+    Iml,
+};
+use serde_json::{json, Map, Value};
+
+/// Reserved [Attachment](crate::Attachment) `payload_type` of DID Document content
+pub const DID_CORE_PAYLOAD_TYPE: &str = "https://www.w3.org/TR/did-core/";
+
+/// DID method prefix of identifiers
+pub const DID_IML_PREFIX: &str = "did:iml:";
+
+/// JSON-LD context of DID Core documents
+const DID_CONTEXT: &str = "https://www.w3.org/ns/did/v1";
+
+/// JSON-LD context of `Multikey` verification methods
+const MULTIKEY_CONTEXT: &str = "https://w3id.org/security/multikey/v1";
+
+impl Iml {
+    /// DID of this identifier - `did:iml:<id>`
+    pub fn did(&self) -> String {
+        format!("{DID_IML_PREFIX}{}", self.id)
+    }
+
+    /// W3C DID Core document of current state.
+    ///
+    /// `current_sk` is listed as `authentication` and `assertionMethod`,
+    ///  interaction key as `keyAgreement`, both as `Multikey` verification methods
+    ///  with stable ids of [VerificationKey](crate::key_formats::VerificationKey).
+    /// Content of attachments with [DID_CORE_PAYLOAD_TYPE] `payload_type` is merged on top
+    ///  and [SERVICE_PAYLOAD_TYPE] service changes are applied,
+    ///  from oldest civilization to the newest one, skipping attachments with invalid proof.
+    /// Arrays are extended, other properties replaced, `id` can not be changed.
+    pub fn to_did_document(&self) -> Result<Value, Error> {
+        let did = self.did();
+        let sk = self.current_sk_key();
+        let ik = self.interaction_verification_key();
+        let mut document = json!({
+            "@context": [DID_CONTEXT, MULTIKEY_CONTEXT],
+            "id": did,
+            "verificationMethod": [
+                sk.to_verification_method(&did)?,
+                ik.to_verification_method(&did)?,
+            ],
+            "authentication": [sk.id],
+            "assertionMethod": [sk.id],
+            "keyAgreement": [ik.id],
+        });
+        let Value::Object(fields) = &mut document else {
+            unreachable!()
+        };
+        for state in &self.history() {
+            for attachment in state.attachments.iter().flatten() {
+                let payload = attachment.get_payload();
+                match attachment.get_payload_type() {
+                    DID_CORE_PAYLOAD_TYPE if attachment.verify(state) => {
+                        match serde_json::from_slice(payload) {
+                            Ok(Value::Object(content)) => merge(fields, content),
+                            _ => return Err(Error::MalformedDocument),
+                        }
+                    }
+                    SERVICE_PAYLOAD_TYPE if attachment.verify(state) => {
+                        serde_json::from_slice::<ServiceChange>(payload)
+                            .map_err(|_| Error::MalformedDocument)?
+                            .apply(fields, &did)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(document)
+    }
+}
+
+/// Merges attached `content` into document `fields`
+fn merge(fields: &mut Map<String, Value>, content: Map<String, Value>) {
+    for (key, value) in content {
+        if key == "id" {
+            continue;
+        }
+        match (fields.get_mut(&key), value) {
+            (Some(Value::Array(existing)), Value::Array(values)) => {
+                for value in values {
+                    if !existing.contains(&value) {
+                        existing.push(value);
+                    }
+                }
+            }
+            (_, value) => {
+                fields.insert(key, value);
+            }
+        }
+    }
+}
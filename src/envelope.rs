@@ -0,0 +1,267 @@
+use crate::{
+    error::Error,
+    wallet::{KeyId, KeyType},
+    SEPARATOR,
+};
+use crypto_secretbox::{
+    aead::{
+        generic_array::{typenum::Unsigned, GenericArray},
+        Aead, AeadCore, KeyInit, OsRng,
+    },
+    XSalsa20Poly1305,
+};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use static_dh_ecdh::ecdh::ecdh::{FromBytes, PkP256};
+use zeroize::Zeroizing;
+
+/// Envelope format version produced by `Iml::interact`.
+/// Version 0 is legacy, where raw shared secret is used as encryption key.
+pub(crate) const ENVELOPE_VERSION: u8 = 1;
+
+/// Protocol label of interaction key derivation
+const KDF_LABEL: &[u8] = b"did:iml/interaction";
+
+/// Protocol label of pairwise key attestation
+const PAIRWISE_LABEL: &[u8] = b"did:iml/pairwise";
+
+/// Appends length prefixed `key` to `buf`
+fn push_key(buf: &mut Vec<u8>, key: &[u8]) {
+    buf.extend((key.len() as u16).to_be_bytes());
+    buf.extend(key);
+}
+
+/// Message signed by sender's current signing key to attest
+///  it's `pairwise` interaction key towards `recipient` key
+pub(crate) fn pairwise_message(pairwise: &[u8], recipient: &[u8]) -> Vec<u8> {
+    let mut message = PAIRWISE_LABEL.to_vec();
+    push_key(&mut message, pairwise);
+    push_key(&mut message, recipient);
+    message
+}
+
+/// AEAD cipher of interaction envelope, chosen by the sender
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Cipher {
+    /// XSalsa20Poly1305 with 24 bytes nonce
+    #[default]
+    XSalsa20Poly1305,
+    /// XChaCha20Poly1305 with 24 bytes nonce
+    XChaCha20Poly1305,
+    /// AES-256-GCM with 12 bytes nonce
+    Aes256Gcm,
+}
+
+fn is_default_cipher(cipher: &Cipher) -> bool {
+    *cipher == Cipher::default()
+}
+
+/// How sender of interaction envelope agrees on the key with recipient
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// Sender's `interaction_key` is used, so recipient knows who sent the envelope
+    #[default]
+    Authcrypt,
+    /// Fresh ephemeral key is used for each envelope, so envelopes are unlinkable
+    Anoncrypt,
+}
+
+fn is_default_mode(mode: &Mode) -> bool {
+    *mode == Mode::default()
+}
+
+impl Cipher {
+    /// Encrypts `plaintext` with random nonce
+    /// # Returns
+    /// - (ciphertext, nonce)
+    pub(crate) fn encrypt(
+        &self,
+        key: &[u8],
+        plaintext: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        match self {
+            Cipher::XSalsa20Poly1305 => encrypt_with::<XSalsa20Poly1305>(key, plaintext),
+            Cipher::XChaCha20Poly1305 => {
+                encrypt_with::<chacha20poly1305::XChaCha20Poly1305>(key, plaintext)
+            }
+            Cipher::Aes256Gcm => encrypt_with::<aes_gcm::Aes256Gcm>(key, plaintext),
+        }
+    }
+
+    /// Decrypts `ciphertext`, failing if `nonce` is of wrong size for the cipher
+    pub(crate) fn decrypt(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        match self {
+            Cipher::XSalsa20Poly1305 => decrypt_with::<XSalsa20Poly1305>(key, nonce, ciphertext),
+            Cipher::XChaCha20Poly1305 => {
+                decrypt_with::<chacha20poly1305::XChaCha20Poly1305>(key, nonce, ciphertext)
+            }
+            Cipher::Aes256Gcm => decrypt_with::<aes_gcm::Aes256Gcm>(key, nonce, ciphertext),
+        }
+    }
+}
+
+fn encrypt_with<A: Aead + AeadCore + KeyInit>(
+    key: &[u8],
+    plaintext: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let nonce = A::generate_nonce(&mut OsRng);
+    let ciphertext = A::new_from_slice(key)?.encrypt(&nonce, plaintext)?;
+    Ok((ciphertext, nonce.to_vec()))
+}
+
+fn decrypt_with<A: Aead + AeadCore + KeyInit>(
+    key: &[u8],
+    nonce: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, Error> {
+    if nonce.len() != A::NonceSize::USIZE {
+        return Err(Error::CryptoBoxError("Invalid nonce size".to_string()));
+    }
+    Ok(A::new_from_slice(key)?.decrypt(GenericArray::from_slice(nonce), ciphertext)?)
+}
+
+/// Interaction envelope parameters.
+/// Carried as hex encoded CBOR in optional last segment of `did:iml`.
+/// Legacy envelopes have no header, which implies [EnvelopeHeader::default].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct EnvelopeHeader {
+    /// Key agreement type of the sender's key
+    #[serde(rename = "kt")]
+    pub key_type: KeyType,
+    /// Envelope format version
+    #[serde(rename = "v", default)]
+    pub version: u8,
+    /// Payload cipher
+    #[serde(rename = "c", default, skip_serializing_if = "is_default_cipher")]
+    pub cipher: Cipher,
+    /// Key agreement mode
+    #[serde(rename = "m", default, skip_serializing_if = "is_default_mode")]
+    pub mode: Mode,
+    /// Id of recipient's interaction key used for key agreement
+    #[serde(rename = "r", default, skip_serializing_if = "Option::is_none")]
+    pub recipient: Option<KeyId>,
+    /// Sender's signature of [pairwise_message], when sender key is pairwise one
+    #[serde(rename = "pp", default, skip_serializing_if = "Option::is_none")]
+    pub pairwise_proof: Option<Vec<u8>>,
+}
+
+impl Default for EnvelopeHeader {
+    fn default() -> Self {
+        Self {
+            key_type: KeyType::EcdhP256,
+            version: 0,
+            cipher: Cipher::default(),
+            mode: Mode::default(),
+            recipient: None,
+            pairwise_proof: None,
+        }
+    }
+}
+
+impl EnvelopeHeader {
+    /// Header of current format version with given parameters
+    pub fn current(key_type: KeyType, cipher: Cipher, mode: Mode) -> Self {
+        Self {
+            key_type,
+            version: ENVELOPE_VERSION,
+            cipher,
+            mode,
+            recipient: None,
+            pairwise_proof: None,
+        }
+    }
+
+    /// Derives encryption key from raw Diffie-Hellman shared secret.
+    /// HKDF-SHA256 info binds protocol label, this header
+    ///  and both parties' public keys, in sender -> recipient order.
+    /// Legacy (version 0) envelopes use shared secret as is.
+    pub fn derive_key(
+        &self,
+        shared: Zeroizing<Vec<u8>>,
+        sender_key: &[u8],
+        recipient_key: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        if self.version == 0 {
+            return Ok(shared);
+        }
+        if self.version > ENVELOPE_VERSION {
+            return Err(Error::NotAnIml);
+        }
+        let mut info = KDF_LABEL.to_vec();
+        info.extend(serde_cbor::to_vec(self)?);
+        push_key(&mut info, sender_key);
+        push_key(&mut info, recipient_key);
+        let mut okm = Zeroizing::new(vec![0u8; 32]);
+        Hkdf::<Sha256>::new(None, &shared)
+            .expand(&info, &mut okm)
+            .map_err(|_| Error::ECDHCryptoError)?;
+        Ok(okm)
+    }
+
+    /// Checks if `key` is valid public key of header's key type
+    fn is_valid_key(&self, key: &[u8]) -> bool {
+        match self.key_type {
+            // too expensive?
+            KeyType::EcdhP256 => PkP256::from_bytes(key).is_ok(),
+            KeyType::X25519 => key.len() == 32,
+            _ => false,
+        }
+    }
+}
+
+/// Parsed `did:iml:<sender key>:<payload>:<nonce>[:<header>]`.
+/// Sender key is pairwise one in [Mode::Authcrypt] and ephemeral one in [Mode::Anoncrypt].
+pub(crate) struct Envelope<'a> {
+    pub sender_key: Vec<u8>,
+    pub payload: &'a str,
+    pub nonce: &'a str,
+    pub header: EnvelopeHeader,
+}
+
+impl<'a> Envelope<'a> {
+    pub fn parse(did: &'a str) -> Result<Self, Error> {
+        let split: Vec<&str> = did.split(SEPARATOR).collect();
+        let len = split.len();
+        if !(5..=6).contains(&len) || split[1] != "iml" {
+            return Err(Error::NotAnIml);
+        }
+        if split[0] != "did" {
+            return Err(Error::NotADid);
+        }
+        let header = match split.get(5) {
+            Some(header) => serde_cbor::from_slice(&hex::decode(header)?)?,
+            None => EnvelopeHeader::default(),
+        };
+        let sender_key = hex::decode(split[2])?;
+        if !header.is_valid_key(&sender_key) {
+            return Err(Error::NotAnIml);
+        }
+        Ok(Self {
+            sender_key,
+            payload: split[3],
+            nonce: split[4],
+            header,
+        })
+    }
+
+    /// Formats `did:iml`, header segment is omitted for legacy envelopes
+    pub fn format(&self) -> Result<String, Error> {
+        let mut did = format!(
+            "did:iml:{}:{}:{}",
+            hex::encode(&self.sender_key),
+            self.payload,
+            self.nonce
+        );
+        if self.header != EnvelopeHeader::default() {
+            did.push(SEPARATOR);
+            did.push_str(&hex::encode(serde_cbor::to_vec(&self.header)?));
+        }
+        Ok(did)
+    }
+}
@@ -15,12 +15,42 @@ pub enum Error {
     KeyExistsForId,
     #[error("Key not found")]
     KeyNotFound,
+    #[error("Key secret was destroyed on retirement")]
+    KeyRetired,
+    #[error("Superseded key is past it's grace period")]
+    KeyExpired,
+    #[error("Key encoding is invalid or does not match key type")]
+    InvalidKeyEncoding,
     #[error("Key type is not supported (yet?)")]
     UnsupportedKeyType,
     #[error("Incorrect did string")]
     NotADid,
     #[error("Incorrect did IML string")]
     NotAnIml,
+    #[error("Identifier was discontinued")]
+    Discontinued,
+    #[error("Iml verification failed")]
+    VerificationFailed,
+    #[error("Service with given id exists already")]
+    ServiceExists,
+    #[error("Service with given id not found")]
+    ServiceNotFound,
+    #[error("Interaction freshness is missing, invalid or expired")]
+    NotFresh,
+    #[error("Interaction was already received")]
+    Replayed,
+    #[error("DIDComm message is malformed or can not be authenticated")]
+    MalformedMessage,
+    #[error("DID Document attachment is malformed")]
+    MalformedDocument,
+    #[error("Locked wallet content is malformed")]
+    MalformedWallet,
+    #[error("Key derivation failed: {0}")]
+    KdfFailed(String),
+    #[error("Key store is locked by another process")]
+    StoreLocked,
+    #[error("Key store failed: {0}")]
+    StoreFailed(String),
     #[error("CryptoBoxError")]
     CryptoBoxError(String),
     #[error(transparent)]
@@ -41,12 +71,37 @@ impl From<InvalidLength> for Error {
     }
 }
 
+impl From<argon2::Error> for Error {
+    fn from(value: argon2::Error) -> Self {
+        Error::KdfFailed(value.to_string())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(value: rusqlite::Error) -> Self {
+        Error::StoreFailed(value.to_string())
+    }
+}
+
+impl From<k256::elliptic_curve::Error> for Error {
+    fn from(_: k256::elliptic_curve::Error) -> Self {
+        Error::InvalidKeyEncoding
+    }
+}
+
 impl From<CborError> for Error {
     fn from(_: CborError) -> Self {
         Error::CborFailed
     }
 }
 
+impl From<coset::CoseError> for Error {
+    fn from(_: coset::CoseError) -> Self {
+        Error::CborFailed
+    }
+}
+
 impl From<CryptoError> for Error {
     fn from(_: CryptoError) -> Self {
         Error::ECDHCryptoError
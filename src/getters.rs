@@ -1,4 +1,7 @@
-use super::{Attachment, Iml};
+use super::{Attachment, Iml, KeyType};
+use crate::error::Error;
+use crate::replay::Freshness;
+use crate::wallet::{key_id_generate, KeyId};
 
 impl Iml {
     pub fn get_civilization(&self) -> u64 {
@@ -10,30 +13,88 @@ impl Iml {
     pub fn get_next_sk(&self) -> &[u8] {
         &self.next_sk
     }
+    pub fn get_sk_type(&self) -> KeyType {
+        self.sk_type
+    }
     pub fn get_interacion_key(&self) -> Vec<u8> {
         self.interaction_key.clone()
     }
+    /// Interaction key currently in use.
+    /// Evolved `Iml`s of older versions do not carry it, so inception one, encoded into `id`, is used.
+    pub(crate) fn active_interaction_key(&self) -> Vec<u8> {
+        if self.interaction_key.is_empty() {
+            hex::decode(&self.id).unwrap_or_default()
+        } else {
+            self.interaction_key.clone()
+        }
+    }
+    /// Discontinued `Iml` has no `next_sk` committed, so can not evolve any further
+    pub fn is_discontinued(&self) -> bool {
+        !self.current_sk.is_empty() && self.next_sk.is_empty()
+    }
+    pub fn get_freshness(&self) -> Option<&Freshness> {
+        self.freshness.as_ref()
+    }
+
+    /// Removes interaction only [Freshness], e.g. before storing received `Iml`
+    pub fn take_freshness(&mut self) -> Option<Freshness> {
+        self.freshness.take()
+    }
+
+    pub fn get_interaction_key_type(&self) -> KeyType {
+        self.interaction_key_type.unwrap_or(KeyType::EcdhP256)
+    }
     // TODO: make it Result and error if cannot reach id
     pub fn get_id(&self) -> String {
         if self.get_civilization() == 0 {
             self.id.clone()
         } else {
             match self.previous() {
-                Some(previous) => previous.get_id(),
-                None => String::default(),
+                Ok(Some(previous)) => previous.get_id(),
+                _ => String::default(),
             }
         }
     }
-    pub fn previous(&self) -> Option<Iml> {
+    /// Previous state, inflated from `inversion`.
+    /// Fails if `inversion` is not a deflated `Iml`.
+    pub fn previous(&self) -> Result<Option<Iml>, Error> {
         self.inversion
             .clone()
-            .map(|previous| Self::inflate(previous, None, None).unwrap())
+            .map(|previous| Self::inflate(previous, None, None))
+            .transpose()
     }
-    pub fn proof(&self) -> Vec<u8> {
-        match self.proof.clone() {
-            Some(p) => p.to_vec(),
-            None => vec![],
+    /// Index of `current_sk` signing key in the wallet, where it is named `sk_<index>`.
+    /// Equals `civilization`, unless there were attachment evolutions, which keep keys.
+    pub(crate) fn key_index(&self) -> u64 {
+        let kept = self
+            .history()
+            .windows(2)
+            .filter(|pair| pair[0].current_sk == pair[1].current_sk)
+            .count() as u64;
+        self.civilization - kept
+    }
+    /// Wallet id of `current_sk` signing key
+    pub(crate) fn controller(&self) -> KeyId {
+        key_id_generate(format!("sk_{}", self.key_index()))
+    }
+    /// All states of this `Iml`, from civilization 0 to self.
+    /// Ends at first malformed `inversion`, which never happens to verified `Iml`.
+    pub fn history(&self) -> Vec<Iml> {
+        let mut history = vec![self.clone()];
+        while let Ok(Some(previous)) = history[history.len() - 1].previous() {
+            history.push(previous);
         }
+        history.reverse();
+        history
+    }
+    /// State of this `Iml` at given civilization
+    pub fn at_civilization(&self, civilization: u64) -> Option<Iml> {
+        self.history()
+            .into_iter()
+            .find(|state| state.get_civilization() == civilization)
+    }
+    pub fn proof(&self) -> Vec<u8> {
+        self.proof.clone().unwrap_or_default()
     }
     pub fn attachments(&self) -> Option<Vec<Attachment>> {
         self.attachments.as_ref().cloned()
@@ -43,6 +104,35 @@ impl Iml {
             attachments: None,
             proof: None,
             inversion: None,
+            freshness: None,
+            ..self.clone()
+        };
+        serde_cbor::to_vec(&verifiable).unwrap()
+    }
+}
+
+impl Attachment {
+    /// New unsigned attachment. Is signed and anchored on [Iml::evolve].
+    pub fn new(payload: impl Into<Vec<u8>>, payload_type: impl ToString) -> Self {
+        Attachment {
+            parent: vec![],
+            payload: payload.into(),
+            payload_type: payload_type.to_string(),
+            proof: None,
+        }
+    }
+    pub fn get_parent(&self) -> &[u8] {
+        &self.parent
+    }
+    pub fn get_payload(&self) -> &[u8] {
+        &self.payload
+    }
+    pub fn get_payload_type(&self) -> &str {
+        &self.payload_type
+    }
+    pub fn as_verifiable(&self) -> Vec<u8> {
+        let verifiable = Attachment {
+            proof: None,
             ..self.clone()
         };
         serde_cbor::to_vec(&verifiable).unwrap()
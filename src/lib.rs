@@ -1,10 +1,23 @@
 use serde::{Deserialize, Serialize};
+pub mod cose;
+pub mod data_signature;
+mod did_key;
+pub mod did_url;
+pub mod didcomm;
+pub mod document;
+mod envelope;
 pub mod error;
 mod getters;
+pub mod key_formats;
 mod packer;
 mod processor;
+pub mod replay;
+pub mod resolver;
+pub mod service;
+pub mod store;
 pub mod wallet;
 
+pub use envelope::{Cipher, Mode};
 pub use packer::*;
 
 pub(crate) use wallet::*;
@@ -16,12 +29,11 @@ mod tests;
 ///
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct Iml {
-    /// Blake 3 hash of first Diffie-Hellman interaction public key of the identifier.
-    /// Never attached to higher level Imls.
+    /// Hex encoded Blake3 hash of civilization 0 `current_sk`, which makes identifier self-certifying.
     /// Can be resolved only if full Iml recoursion is parsable.
     ///
-    /// Additionally used during interaction on public Iml to identify
-    ///  recepient's Public key for KeyAgreement.
+    /// Identifiers created before were hex of first interaction public key,
+    ///  those verify only with legacy flag of [Iml::verify_with].
     ///
     pub id: String,
     /// Indicates "age" of identifier.
@@ -35,11 +47,20 @@ pub struct Iml {
     /// Next ECDSA signing public key
     ///
     next_sk: Vec<u8>,
-    /// Current interaction DH agreement public key
-    /// If this property is present - `id` pionts out
-    ///  which key to use to generate shared secret.
+    /// Type of `current_sk` and `next_sk` keys.
+    /// Omitted for secp256k1 ECDSA keys, so legacy `Iml`s verify as before.
+    ///
+    #[serde(default, skip_serializing_if = "is_legacy_sk_type")]
+    sk_type: KeyType,
+    /// Current interaction DH agreement public key.
+    /// Sender key of authcrypt interaction envelopes.
     ///
     interaction_key: Vec<u8>,
+    /// Type of `interaction_key`.
+    /// Omitted for NIST P-256 keys, so legacy `Iml`s verify as before.
+    ///
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    interaction_key_type: Option<KeyType>,
     /// Any usefull payload.
     /// Is not included into verification of Iml, but
     ///  has proof of it's own internally, therefore
@@ -66,9 +87,20 @@ pub struct Iml {
     ///
     #[serde(skip_serializing_if = "Option::is_none")]
     inversion: Option<String>,
-    /// ECDSA signature of rest of the Iml this proof and attachments excluded
+    /// Signature of rest of the Iml this proof and attachments excluded,
+    ///  made with `sk_type` key.
+    ///
+    proof: Option<Vec<u8>>,
+    /// Signed freshness of interaction Iml.
+    /// Present only in interactions and excluded from `proof`,
+    ///  as it has proof of it's own.
     ///
-    proof: Option<Signature>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    freshness: Option<replay::Freshness>,
+}
+
+fn is_legacy_sk_type(sk_type: &KeyType) -> bool {
+    *sk_type == KeyType::Ed25519_256
 }
 
 /// Attachment structure.
@@ -78,7 +110,7 @@ pub struct Iml {
 pub struct Attachment {
     /// `proof` of parent Iml
     ///
-    parent: Signature,
+    parent: Vec<u8>,
     /// Useful data itself
     ///
     payload: Vec<u8>,
@@ -90,7 +122,7 @@ pub struct Attachment {
     /// All IANA registered official mime media types: <https://www.iana.org/assignments/media-types/media-types.xhtml>
     ///
     payload_type: String,
-    /// ECDSA signature of rest of the Attachment.
+    /// Signature of rest of the Attachment.
     /// This proof is excluded from signature generation
     ///  and should not be included for correct verification.
     /// `current_sk` is used for signing from holding `Iml`.
@@ -1,208 +1,650 @@
 use super::{Attachment, Iml, KeyType, UnlockedWallet};
+use crate::did_key::key_agreement_of;
+use crate::envelope::{pairwise_message, Cipher, Envelope, EnvelopeHeader, Mode};
+use crate::replay::Freshness;
 use crate::{
     error::Error,
-    wallet::{key_id_generate, KeyId},
-};
-use crypto_secretbox::{
-    aead::{generic_array::GenericArray, Aead, AeadCore, KeyInit, OsRng},
-    Nonce, XSalsa20Poly1305,
+    wallet::{key_id_generate, KeyId, StateProof},
 };
 use libflate::deflate::{Decoder, Encoder};
-use static_dh_ecdh::ecdh::ecdh::{FromBytes, PkP256};
 use std::io::{Read, Write};
+use zeroize::Zeroizing;
 
 /// DID parts separator
 pub const SEPARATOR: char = ':';
 
+/// Parameters of [Iml::interact_with]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InteractOptions {
+    /// Payload AEAD cipher
+    pub cipher: Cipher,
+    /// Key agreement mode
+    pub mode: Mode,
+    /// Challenge supplied by recipient, signed back within [Freshness]
+    pub challenge: Option<Vec<u8>>,
+}
+
+/// `Iml` unpacked from `did:iml` with keys the envelope was exchanged with
+pub(crate) struct Unpacked {
+    pub iml: Iml,
+    /// Authcrypt sender key, if attested by `iml`
+    pub sender_key: Option<Vec<u8>>,
+    /// Our interaction public key envelope was decrypted with, if encrypted
+    pub recipient_key: Option<Vec<u8>>,
+}
+
+/// Key agreement type and public key of interaction recipient with identifier of the peer.
+/// Non-Iml peers are known by their DID only.
+pub(crate) fn recipient_key(
+    their_did: &str,
+    wallet: &UnlockedWallet,
+) -> Result<(KeyType, Vec<u8>, String), Error> {
+    if let Some((key_type, public)) = key_agreement_of(their_did)? {
+        return Ok((key_type, public, their_did.to_string()));
+    }
+    let Unpacked {
+        iml: them,
+        sender_key,
+        ..
+    } = Iml::unpack(their_did, wallet)?;
+    if !them.verify() {
+        return Err(Error::VerificationFailed);
+    }
+    // attested sender key may be pairwise one
+    let their_pk = sender_key.unwrap_or_else(|| them.active_interaction_key());
+    Ok((them.get_interaction_key_type(), their_pk, them.get_id()))
+}
+
 impl Iml {
-    /// Instantiates new, fully fresh, instance.
+    /// Instantiates new, fully fresh, instance with secp256k1 ECDSA signing keys
+    ///  and NIST P-256 interaction key.
     /// WARN: Given wallet's `sk_0` and `sk_1` values will be overwritten if exist!
     pub fn new(wallet: &mut UnlockedWallet) -> Result<Self, Error> {
+        Self::new_with(wallet, KeyType::Ed25519_256, KeyType::EcdhP256)
+    }
+
+    /// Instantiates new, fully fresh, instance with keys of given types.
+    /// WARN: Given wallet's `sk_0` and `sk_1` values will be overwritten if exist!
+    ///
+    /// # Parameters
+    ///
+    /// * `wallet` - Keywault to generate keys in
+    /// * `sk_type` - signing keys type
+    /// * `interaction_type` - interaction key agreement key type
+    ///
+    pub fn new_with(
+        wallet: &mut UnlockedWallet,
+        sk_type: KeyType,
+        interaction_type: KeyType,
+    ) -> Result<Self, Error> {
+        if !sk_type.is_signing() || !interaction_type.is_agreement() {
+            return Err(Error::UnsupportedKeyType);
+        }
         let current_sk_id = key_id_generate("sk_0");
-        wallet.new_key(KeyType::Ed25519_256, Some(current_sk_id))?;
+        wallet.new_key(sk_type, Some(current_sk_id))?;
         let next_sk_id = key_id_generate("sk_1");
-        wallet.new_key(KeyType::Ed25519_256, Some(next_sk_id))?;
+        wallet.new_key(sk_type, Some(next_sk_id))?;
         let current_sk = wallet
-            .public_for(&current_sk_id, KeyType::Ed25519_256)
+            .public_for(&current_sk_id, sk_type)
             .ok_or(Error::EcdsaFailed)?
             .to_vec();
         let next_sk = wallet
-            .public_for(&next_sk_id, KeyType::Ed25519_256)
+            .public_for(&next_sk_id, sk_type)
             .ok_or(Error::EcdsaFailed)?
             .to_vec();
-        let new_dh_id = wallet.new_key(KeyType::EcdhP256, None)?;
+        let new_dh_id = wallet.new_interaction_key(interaction_type, 0)?;
         let new_dh_pub = wallet
-            .public_for(&new_dh_id, KeyType::EcdhP256)
+            .public_for(&new_dh_id, interaction_type)
             .ok_or(Error::ECDHCryptoError)?;
-        let id = hex::encode(&new_dh_pub);
+        let id = Self::derive_id(&current_sk);
         let mut pre_signed = Iml {
             id,
             current_sk,
             next_sk,
+            sk_type,
             interaction_key: new_dh_pub,
+            interaction_key_type: Some(interaction_type).filter(|t| *t != KeyType::EcdhP256),
             ..Iml::default()
         };
-        let sig = wallet
-            .sign_with(pre_signed.as_verifiable(), &current_sk_id)
-            .unwrap();
+        let sig = wallet.sign_as(pre_signed.as_verifiable(), &current_sk_id, sk_type)?;
         pre_signed.proof = Some(sig);
         Ok(pre_signed)
     }
 
+    /// Evolves self into next civilization.
+    /// Superseded signing key is retired according to wallet's [KeyRetention](crate::wallet::KeyRetention) policy.
+    /// Discontinued `Iml` is returned as is.
+    /// Use [Iml::evolve_tracked] if wallet is persisted record by record.
     pub fn evolve(
         self,
         wallet: &mut UnlockedWallet,
         evolve_sk: bool,
         attachments: Option<Vec<Attachment>>,
     ) -> Self {
-        if !evolve_sk && attachments.is_none() {
-            return self;
+        self.evolve_tracked(wallet, evolve_sk, attachments).0
+    }
+
+    /// Same as [Iml::evolve], also returning ids of wallet keys generated or retired by evolution.
+    /// Each of them must be saved with [UnlockedWallet::save_key](crate::wallet::UnlockedWallet::save_key),
+    ///  otherwise retired secret stays in the [KeyStore](crate::store::KeyStore).
+    pub fn evolve_tracked(
+        self,
+        wallet: &mut UnlockedWallet,
+        evolve_sk: bool,
+        attachments: Option<Vec<Attachment>>,
+    ) -> (Self, Vec<KeyId>) {
+        if self.is_discontinued() || !evolve_sk && attachments.is_none() {
+            return (self, vec![]);
         }
-        let mut evolved = Iml::default();
-        evolved.civilization = self.get_civilization() + 1;
-        evolved.inversion = Some(self.deflate(None).unwrap().0);
-        evolved.id = self.id;
+        let interaction_key = self.interaction_key.clone();
+        self.evolve_into(wallet, evolve_sk, attachments, interaction_key)
+    }
+
+    /// Evolves self into next civilization with fresh interaction key of the same type.
+    /// Superseded interaction key is still accepted for decryption
+    ///  for wallet's [interaction_grace](UnlockedWallet::interaction_grace).
+    pub fn rotate_interaction_key(self, wallet: &mut UnlockedWallet) -> Result<Self, Error> {
+        if self.is_discontinued() {
+            return Err(Error::Discontinued);
+        }
+        let key_type = self.get_interaction_key_type();
+        let new_id = wallet.rotate_interaction_key(
+            &key_id_generate(self.active_interaction_key()),
+            key_type,
+            self.get_civilization() + 1,
+        )?;
+        let interaction_key = wallet
+            .public_for(&new_id, key_type)
+            .ok_or(Error::ECDHCryptoError)?;
+        Ok(self.evolve_into(wallet, true, None, interaction_key).0)
+    }
+
+    /// Discontinues identifier: evolves self into final civilization without `next_sk`,
+    ///  so it can not be evolved any further.
+    /// Both superseded and final signing keys are retired.
+    pub fn discontinue(self, wallet: &mut UnlockedWallet) -> Result<Self, Error> {
+        if self.is_discontinued() {
+            return Err(Error::Discontinued);
+        }
+        let mut discontinued = Iml {
+            civilization: self.get_civilization() + 1,
+            inversion: Some(self.deflate(None)?.0),
+            id: self.id.clone(),
+            current_sk: self.next_sk.clone(),
+            sk_type: self.sk_type,
+            interaction_key: self.interaction_key.clone(),
+            interaction_key_type: self.interaction_key_type,
+            ..Iml::default()
+        };
+        let key_index = self.key_index();
+        let final_controller = key_id_generate(format!("sk_{}", key_index + 1));
+        discontinued.proof = Some(wallet.sign_as(
+            discontinued.as_verifiable(),
+            &final_controller,
+            self.sk_type,
+        )?);
+        wallet.retire_key(
+            &key_id_generate(format!("sk_{key_index}")),
+            self.sk_type,
+            self.controller_proofs(),
+        )?;
+        wallet.retire_key(
+            &final_controller,
+            self.sk_type,
+            discontinued.controller_proofs(),
+        )?;
+        Ok(discontinued)
+    }
+
+    fn evolve_into(
+        self,
+        wallet: &mut UnlockedWallet,
+        evolve_sk: bool,
+        attachments: Option<Vec<Attachment>>,
+        interaction_key: Vec<u8>,
+    ) -> (Self, Vec<KeyId>) {
+        let parent = self.proof();
+        let key_index = self.key_index();
+        let previous_proofs = self.controller_proofs();
+        let mut evolved = Iml {
+            civilization: self.get_civilization() + 1,
+            inversion: Some(self.deflate(None).unwrap().0),
+            id: self.id,
+            sk_type: self.sk_type,
+            interaction_key,
+            interaction_key_type: self.interaction_key_type,
+            ..Iml::default()
+        };
+        // previous controller, kept on attachment evolution
+        let previous_controller = key_id_generate(format!("sk_{key_index}"));
         // becomes current
-        let current_controller = key_id_generate(format!("sk_{}", evolved.get_civilization()));
+        let current_controller = if evolve_sk {
+            key_id_generate(format!("sk_{}", key_index + 1))
+        } else {
+            previous_controller
+        };
         // becomes next for new current
-        let next_controller =
-            key_id_generate(format!("sk_{}", evolved.get_civilization() + 1).into_bytes());
+        let next_controller = key_id_generate(format!("sk_{}", key_index + 2).into_bytes());
         if evolve_sk {
-            wallet.new_key_for(next_controller).unwrap();
+            wallet
+                .new_typed_key_for(self.sk_type, next_controller)
+                .unwrap();
             let new_next = wallet
-                .public_for(&next_controller, KeyType::Ed25519_256)
+                .public_for(&next_controller, self.sk_type)
                 .unwrap()
                 .clone();
             // new next
             evolved.next_sk = new_next.to_vec();
             // new current is old next
             evolved.current_sk = self.next_sk;
+        } else {
+            evolved.current_sk = self.current_sk;
+            evolved.next_sk = self.next_sk;
+        }
+        // attachments are signed by new current and anchored to previous state
+        if let Some(attachments) = attachments {
+            let attachments = attachments
+                .into_iter()
+                .map(|mut attachment| {
+                    attachment.parent = parent.clone();
+                    attachment.proof = Some(
+                        wallet
+                            .sign_as(
+                                attachment.as_verifiable(),
+                                &current_controller,
+                                self.sk_type,
+                            )
+                            .unwrap(),
+                    );
+                    attachment
+                })
+                .collect::<Vec<_>>();
+            evolved.proof_of_attachments = Some(
+                wallet
+                    .sign_as(
+                        serde_cbor::to_vec(&attachments).unwrap(),
+                        &current_controller,
+                        self.sk_type,
+                    )
+                    .unwrap(),
+            );
+            evolved.attachments = Some(attachments);
         }
         // new proof with new current
         let proof = wallet
-            .sign_with(evolved.as_verifiable(), &current_controller)
+            .sign_as(evolved.as_verifiable(), &current_controller, self.sk_type)
             .unwrap();
         evolved.proof = Some(proof);
-        evolved
+        // previous controller is superseded now
+        let mut changed = vec![];
+        if evolve_sk {
+            changed.push(next_controller);
+            if wallet
+                .retire_key(&previous_controller, self.sk_type, previous_proofs)
+                .unwrap()
+            {
+                changed.push(previous_controller);
+            }
+        }
+        (evolved, changed)
+    }
+
+    /// Signatures of self and preceding states made by current signing key,
+    ///  several if it was kept by attachment evolutions
+    fn controller_proofs(&self) -> Vec<StateProof> {
+        let mut proofs = vec![];
+        let mut state = Some(self.clone());
+        while let Some(signed) = state.filter(|state| state.current_sk == self.current_sk) {
+            proofs.push(StateProof {
+                civilization: signed.get_civilization(),
+                proof: signed.proof(),
+                proof_of_attachments: signed.proof_of_attachments.clone(),
+            });
+            state = signed.previous().ok().flatten();
+        }
+        proofs
     }
 
     /// Rebuilds entire state of Iml based on keys present in wallet and ID.
+    /// Attachment evolutions leave no keys, so they are reproduced only from given signed attachments:
+    ///  attachment is re-attached to the state following the one it's `parent` refers to,
+    ///  which keeps keys if attachment is signed by current signing key.
     ///
     /// # Parameters
     ///
     /// * `wallet` - Keywault with keys present for given id
     /// * `id` - identifier of Iml to be restored
-    /// * `attachments` - optional attachments to be re-attached
+    /// * `attachments` - optional signed attachments to be re-attached, e.g. of all [Iml::history] states
     ///
     pub fn re_evolve(
         wallet: &UnlockedWallet,
         id: impl AsRef<str> + ToString,
-        _attachments: Option<Vec<Attachment>>,
+        attachments: Option<Vec<Attachment>>,
     ) -> Self {
-        // TODO: re-attach attachments
-        let mut iml = Iml::default();
-        iml.id = id.to_string();
+        let attachments = attachments.unwrap_or_default();
+        let mut iml = Iml {
+            id: id.to_string(),
+            sk_type: wallet
+                .key_type(&key_id_generate("sk_0"))
+                .unwrap_or_default(),
+            ..Iml::default()
+        };
+        iml.restore(wallet, true, vec![]);
         loop {
-            iml.restore(wallet);
-            if wallet
+            let parent = iml.proof();
+            let anchored: Vec<Attachment> = attachments
+                .iter()
+                .filter(|attachment| attachment.parent == parent)
+                .cloned()
+                .collect();
+            let keeps_keys = !anchored.is_empty()
+                && anchored.iter().all(|attachment| {
+                    attachment.proof.as_ref().is_some_and(|proof| {
+                        iml.sk_type
+                            .verify(iml.get_current_sk(), &attachment.as_verifiable(), proof)
+                    })
+                });
+            let evolved_sk = wallet
                 .public_for(
-                    &key_id_generate(format!("sk_{}", iml.get_civilization() + 2)),
-                    KeyType::Ed25519_256,
+                    &key_id_generate(format!("sk_{}", iml.key_index() + 2)),
+                    iml.sk_type,
                 )
-                .is_none()
-            {
+                .is_some();
+            if !keeps_keys && !evolved_sk {
+                break;
+            }
+            let civilization = iml.get_civilization();
+            iml.restore(wallet, !keeps_keys, anchored);
+            if iml.get_civilization() == civilization {
                 break;
             }
         }
         iml
     }
 
-    /// Pack-encrypt self for target did with only selected attachments (or none)
+    /// Pack-encrypt self for target did with only selected attachments (or none).
+    /// Target is interaction `did:iml` of the peer or, for non-Iml peers, `did:key`, `did:peer:0`
+    ///  or `did:peer:2` with key agreement key.
+    /// Dedicated pairwise interaction key is generated for the peer on first interaction
+    ///  and remembered in the wallet, so each peer sees only it's own key.
     pub fn interact(
         &self,
-        wallet: &UnlockedWallet,
+        wallet: &mut UnlockedWallet,
         their_did: impl AsRef<str>,
         attachments: Option<Vec<Attachment>>,
     ) -> Result<String, Error> {
-        let them = Iml::from_did(
-            their_did,
-            wallet,
-            key_id_generate(self.get_interacion_key()),
-        )?;
-        let their_pk = them.get_interacion_key();
-        let mut to_pack = self.clone();
+        self.interact_with(wallet, their_did, attachments, InteractOptions::default())
+    }
+
+    /// Pack-encrypt self for target did with only selected attachments (or none)
+    ///  and given [InteractOptions].
+    /// Packed Iml is stamped with signed [Freshness] for replay protection.
+    /// In [Mode::Anoncrypt] fresh ephemeral key is generated for each call,
+    ///  so envelope is not linkable to any key of self.
+    /// In either mode packed Iml is a per-peer view, carrying pairwise interaction key
+    ///  instead of `interaction_key` of self, re-signed by current signing key.
+    pub fn interact_with(
+        &self,
+        wallet: &mut UnlockedWallet,
+        their_did: impl AsRef<str>,
+        attachments: Option<Vec<Attachment>>,
+        options: InteractOptions,
+    ) -> Result<String, Error> {
+        let InteractOptions {
+            cipher,
+            mode,
+            challenge,
+        } = options;
+        let (their_type, their_pk, peer) = recipient_key(their_did.as_ref(), wallet)?;
+        let key_type = self.get_interaction_key_type();
+        let pairwise_id = wallet.pairwise_key(&peer, key_type)?;
+        let mut to_pack = self.pairwise_view(wallet, &pairwise_id)?;
         to_pack.attachments = attachments;
-        let dx = self.diffie_hellman(wallet, their_pk)?;
-        to_pack.as_did(Some(dx.to_vec()))
+        to_pack.freshness = Some(Freshness::new(&to_pack, wallet, challenge, &their_pk)?);
+        let mut header = EnvelopeHeader {
+            recipient: Some(key_id_generate(&their_pk)),
+            ..EnvelopeHeader::current(their_type, cipher, mode)
+        };
+        let (sender_key, dx) = match mode {
+            Mode::Authcrypt => {
+                if their_type != key_type {
+                    return Err(Error::UnsupportedKeyType);
+                }
+                let pairwise = wallet
+                    .public_for(&pairwise_id, key_type)
+                    .ok_or(Error::ECDHCryptoError)?;
+                header.pairwise_proof = Some(wallet.sign_as(
+                    pairwise_message(&pairwise, &their_pk),
+                    &self.controller(),
+                    self.get_sk_type(),
+                )?);
+                let dx = wallet.diffie_hellman_as(&pairwise_id, &their_pk, key_type)?;
+                (pairwise, dx)
+            }
+            Mode::Anoncrypt => their_type.ephemeral_agreement(&their_pk)?,
+        };
+        let key = header.derive_key(dx, &sender_key, &their_pk)?;
+        to_pack.seal(sender_key, Some(key), header)
     }
 
+    /// Copy of self with `interaction_key` replaced by pairwise key `pairwise_id`,
+    ///  re-signed by current signing key, so it verifies as state of self.
+    /// Chain of previous states is kept as is, so views of the same state are linkable by peers.
+    pub(crate) fn pairwise_view(
+        &self,
+        wallet: &UnlockedWallet,
+        pairwise_id: &KeyId,
+    ) -> Result<Self, Error> {
+        let key_type = self.get_interaction_key_type();
+        let mut view = Iml {
+            interaction_key: wallet
+                .public_for(pairwise_id, key_type)
+                .ok_or(Error::KeyNotFound)?,
+            ..self.clone()
+        };
+        view.proof =
+            Some(wallet.sign_as(view.as_verifiable(), &self.controller(), self.get_sk_type())?);
+        Ok(view)
+    }
+
+    /// Unpacks `did:iml` decrypting it, if encrypted, with `our_id` interaction key
     pub fn from_did(
         did: impl AsRef<str>,
         wallet: &UnlockedWallet,
         our_id: KeyId,
     ) -> Result<Self, Error> {
-        let split: Vec<&str> = did.as_ref().split(SEPARATOR).collect();
-        let len = split.len();
-        if len != 5
-            || split[1] != "iml"
-            // too expensive?
-            || PkP256::from_bytes(&hex::decode(split[2])?).is_err()
-        {
-            return Err(Error::NotAnIml);
+        Self::open(&Envelope::parse(did.as_ref())?, wallet, &our_id).map(|unpacked| unpacked.iml)
+    }
+
+    /// Unpacks `did:iml` selecting our interaction key by recipient key reference of the envelope.
+    /// If reference is absent, every interaction key of matching type in the wallet is tried.
+    pub fn from_did_any(did: impl AsRef<str>, wallet: &UnlockedWallet) -> Result<Self, Error> {
+        Self::unpack(did, wallet).map(|unpacked| unpacked.iml)
+    }
+
+    /// Same as [Iml::from_did_any], also returning keys the envelope was exchanged with.
+    pub(crate) fn unpack(did: impl AsRef<str>, wallet: &UnlockedWallet) -> Result<Unpacked, Error> {
+        let envelope = Envelope::parse(did.as_ref())?;
+        if envelope.nonce.is_empty() {
+            return Ok(Unpacked {
+                iml: Self::inflate(envelope.payload, None, None)?,
+                sender_key: None,
+                recipient_key: None,
+            });
         }
-        if split[0] != "did" {
-            return Err(Error::NotADid);
+        if let Some(recipient) = envelope.header.recipient {
+            return Self::open(&envelope, wallet, &recipient);
         }
+        let key_type = envelope.header.key_type;
+        wallet
+            .key_ids()
+            .iter()
+            // untyped keys are NIST P-256 if usable for key agreement at all
+            .filter(|id| wallet.key_type(id).unwrap_or(KeyType::EcdhP256) == key_type)
+            .find_map(|id| Self::open(&envelope, wallet, id).ok())
+            .ok_or(Error::KeyNotFound)
+    }
+
+    /// Opens envelope with `our_id` interaction key.
+    /// Authcrypt sender key is returned only if attested by the opened `Iml`.
+    fn open(
+        envelope: &Envelope,
+        wallet: &UnlockedWallet,
+        our_id: &KeyId,
+    ) -> Result<Unpacked, Error> {
         // Authcrypt...
-        if !split[4].is_empty() {
-            let nonce = Nonce::from_slice(&hex::decode(split[4])?).to_owned();
-            let dx = wallet.diffie_hellman(&our_id, hex::decode(split[2])?)?;
-            Self::inflate(split[3], Some(dx), Some(nonce))
+        if !envelope.nonce.is_empty() {
+            let nonce = hex::decode(envelope.nonce)?;
+            let key_type = envelope.header.key_type;
+            let dx = wallet.diffie_hellman_as(our_id, &envelope.sender_key, key_type)?;
+            let our_key = wallet
+                .public_for(our_id, key_type)
+                .ok_or(Error::KeyNotFound)?;
+            let key = envelope
+                .header
+                .derive_key(dx, &envelope.sender_key, &our_key)?;
+            let iml = Self::inflate(
+                envelope.payload,
+                Some((key, envelope.header.cipher)),
+                Some(&nonce),
+            )?;
+            // authcrypt sender must be the one who agreed on the key,
+            //  by attested pairwise key or, if not attested, directly.
+            // Legacy envelopes have no attestation, so only direct agreement is accepted
+            let legacy = envelope.header.version == 0;
+            let attested = match envelope.header.pairwise_proof.as_ref() {
+                Some(proof) if !legacy => iml.get_sk_type().verify(
+                    iml.get_current_sk(),
+                    &pairwise_message(&envelope.sender_key, &our_key),
+                    proof,
+                ),
+                _ => iml.active_interaction_key() == envelope.sender_key,
+            };
+            let authcrypt = legacy || envelope.header.mode == Mode::Authcrypt;
+            if authcrypt && !attested {
+                return Err(Error::NotAnIml);
+            }
+            Ok(Unpacked {
+                iml,
+                sender_key: authcrypt.then(|| envelope.sender_key.clone()),
+                recipient_key: Some(our_key),
+            })
         } else {
             // RAW!!!
-            Self::inflate(split[3], None, None)
+            Ok(Unpacked {
+                iml: Self::inflate(envelope.payload, None, None)?,
+                sender_key: None,
+                recipient_key: None,
+            })
         }
     }
 
-    pub fn as_did(&self, dh: Option<Vec<u8>>) -> Result<String, Error> {
-        let (deflated, nonce) = self.deflate(dh)?;
-        Ok(format!("did:iml:{}:{}:{}", self.id, deflated, nonce))
+    /// Packs self into `did:iml` of legacy format version,
+    ///  where `dh`, if present, is used as encryption key as is.
+    /// Use [Iml::interact] for key derived from shared secret.
+    pub fn as_did(&self, dh: Option<Zeroizing<Vec<u8>>>) -> Result<String, Error> {
+        self.seal(
+            self.active_interaction_key(),
+            dh,
+            EnvelopeHeader {
+                key_type: self.get_interaction_key_type(),
+                ..EnvelopeHeader::default()
+            },
+        )
     }
 
-    fn restore(&mut self, wallet: &UnlockedWallet) {
-        let mut iml = Iml::default();
-        if self.get_civilization() == 0 && !self.get_current_sk().is_empty() {
-            iml.civilization = self.get_civilization() + 1;
+    fn seal(
+        &self,
+        sender_key: Vec<u8>,
+        key: Option<Zeroizing<Vec<u8>>>,
+        header: EnvelopeHeader,
+    ) -> Result<String, Error> {
+        let cipher = header.cipher;
+        let (deflated, nonce) = self.deflate(key.map(|key| (key, cipher)))?;
+        Envelope {
+            sender_key,
+            payload: &deflated,
+            nonce: &nonce,
+            header,
+        }
+        .format()
+    }
+
+    /// Restores next civilization of self from wallet keys, with given signed attachments.
+    /// Signing keys are kept, unless `evolve_sk`.
+    /// Empty self is restored into civilization 0.
+    fn restore(&mut self, wallet: &UnlockedWallet, evolve_sk: bool, attachments: Vec<Attachment>) {
+        let mut iml = Iml {
+            id: self.id.clone(),
+            sk_type: self.sk_type,
+            interaction_key_type: self.interaction_key_type,
+            ..Iml::default()
+        };
+        if self.get_current_sk().is_empty() {
+            let (key_type, interaction_key) = match wallet.interaction_key_at(0) {
+                Some(key) => key,
+                None => {
+                    // legacy inception: `id` is hex of interaction key
+                    let public = hex::decode(&self.id).unwrap_or_default();
+                    let key_type = wallet
+                        .key_type(&key_id_generate(&public))
+                        .unwrap_or(KeyType::EcdhP256);
+                    (key_type, public)
+                }
+            };
+            iml.interaction_key = interaction_key;
+            iml.interaction_key_type =
+                Some(key_type).filter(|t| t.is_agreement() && *t != KeyType::EcdhP256);
         } else {
-            iml.id = self.id.clone()
+            iml.civilization = self.get_civilization() + 1;
+            iml.inversion = Some(self.deflate(None).unwrap().0);
+            iml.interaction_key = wallet
+                .interaction_key_at(iml.civilization)
+                .map(|(_, public)| public)
+                .unwrap_or_else(|| self.interaction_key.clone());
         }
-        if let Some(current) = wallet.public_for(
-            &key_id_generate(format!("sk_{}", iml.get_civilization())),
-            KeyType::Ed25519_256,
+        let key_index = if self.get_current_sk().is_empty() {
+            0
+        } else {
+            self.key_index() + u64::from(evolve_sk)
+        };
+        let current_id = key_id_generate(format!("sk_{key_index}"));
+        let next_id = key_id_generate(format!("sk_{}", key_index + 1));
+        if let (Some(current), Some(next)) = (
+            wallet.public_for(&current_id, iml.sk_type),
+            wallet.public_for(&next_id, iml.sk_type),
         ) {
-            let next_id = key_id_generate(format!("sk_{}", iml.get_civilization() + 1));
-            if let Some(next) = wallet.public_for(&next_id, KeyType::Ed25519_256) {
-                if iml.get_civilization() > 0 {
-                    iml.inversion = Some(self.deflate(None).unwrap().0);
-                }
-                iml.current_sk = current.to_vec();
-                iml.next_sk = next.to_vec();
-                iml.proof = Some(wallet.sign_with(iml.as_verifiable(), &next_id).unwrap());
-                *self = iml;
+            iml.current_sk = current;
+            iml.next_sk = next;
+            // secret may be gone - proofs are kept on retirement
+            let retired = wallet.retired_proof(&current_id, iml.civilization);
+            if !attachments.is_empty() {
+                iml.proof_of_attachments = serde_cbor::to_vec(&attachments)
+                    .ok()
+                    .and_then(|content| wallet.sign_as(content, &current_id, iml.sk_type).ok())
+                    .or_else(|| retired.as_ref()?.proof_of_attachments.clone());
+                iml.attachments = Some(attachments);
             }
+            iml.proof = match wallet.sign_as(iml.as_verifiable(), &current_id, iml.sk_type) {
+                Ok(proof) => Some(proof),
+                Err(_) => retired.map(|state| state.proof),
+            };
+            *self = iml;
         }
     }
 
     /// Returns deflated, serialized and encrypted self + nonce
-    fn deflate(&self, encrypt: Option<Vec<u8>>) -> Result<(String, String), Error> {
+    pub(crate) fn deflate(
+        &self,
+        encrypt: Option<(Zeroizing<Vec<u8>>, Cipher)>,
+    ) -> Result<(String, String), Error> {
         // serialize
         let mut serialized = serde_cbor::to_vec(&self).unwrap();
         let mut nonce_string = String::default();
         // encrypt
-        if let Some(dh) = encrypt {
-            let nonce = XSalsa20Poly1305::generate_nonce(&mut OsRng);
-            let cypher = XSalsa20Poly1305::new(GenericArray::from_slice(dh.as_ref()));
-            serialized = cypher.encrypt(&nonce, serialized.as_ref())?;
+        if let Some((key, cipher)) = encrypt {
+            let (encrypted, nonce) = cipher.encrypt(&key, &serialized)?;
+            serialized = encrypted;
             nonce_string = hex::encode(nonce);
         }
         // deflate
@@ -215,8 +657,8 @@ impl Iml {
 
     pub(crate) fn inflate(
         data: impl AsRef<str>,
-        decrypt: Option<Vec<u8>>,
-        nonce: Option<Nonce>,
+        decrypt: Option<(Zeroizing<Vec<u8>>, Cipher)>,
+        nonce: Option<&[u8]>,
     ) -> Result<Self, Error> {
         // unhex
         let decoded_bytes = hex::decode(data.as_ref())?;
@@ -225,10 +667,9 @@ impl Iml {
         let mut decoded = Vec::new();
         decoder.read_to_end(&mut decoded)?;
         // decrypt
-        if let Some(dh) = decrypt {
+        if let Some((key, cipher)) = decrypt {
             let nonce = nonce.ok_or(Error::CryptoBoxError("Nonce is required".to_string()))?;
-            let cypher = XSalsa20Poly1305::new(GenericArray::from_slice(&dh));
-            decoded = cypher.decrypt(&nonce, decoded.as_ref())?;
+            decoded = cipher.decrypt(&key, nonce, &decoded)?;
         }
         // de-serialize
         Ok(serde_cbor::from_slice(&decoded)?)
@@ -257,9 +698,8 @@ fn new_iml_plus_verification_test() {
     let inflated = Iml::inflate(deflated.0, None, None).unwrap();
     assert_eq!(iml, inflated);
 
-    //TODO: fix >1 evolution
-    //let restored = Iml::re_evolve(&wallet, &iml.get_id(), None);
-    //assert_eq!(iml, restored);
+    let restored = Iml::re_evolve(&wallet, iml.get_id(), None);
+    assert_eq!(iml, restored);
 }
 
 #[test]
@@ -276,7 +716,7 @@ fn interact_proper_test() -> Result<(), Error> {
         &key_id_generate(b.get_interacion_key()), //4bc
         a.get_interacion_key(),
     )?))?;
-    assert!(a.interact(&a_wallet, b_did, None).is_ok());
-    assert!(b.interact(&b_wallet, a_did, None).is_ok());
+    assert!(a.interact(&mut a_wallet, b_did, None).is_ok());
+    assert!(b.interact(&mut b_wallet, a_did, None).is_ok());
     Ok(())
 }
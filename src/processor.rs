@@ -3,20 +3,42 @@ use crate::{
     wallet::{key_id_generate, UnlockedWallet},
 };
 
-use super::Iml;
-use k256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use super::{Attachment, Iml};
+use zeroize::Zeroizing;
 
 impl Iml {
+    /// Verifies entire chain. Identifier must be derived from initial signing key.
     pub fn verify(&self) -> bool {
+        self.verify_with(false)
+    }
+
+    /// Verifies entire chain.
+    ///
+    /// # Parameters
+    ///
+    /// * `legacy_id` - also accept identifiers derived from initial interaction key,
+    ///   as created by older versions
+    ///
+    pub fn verify_with(&self, legacy_id: bool) -> bool {
         match self.previous() {
-            Some(previous) => {
-                if previous.get_civilization() + 1 != self.civilization {
+            Ok(Some(previous)) => {
+                if previous.get_civilization() + 1 != self.civilization || previous.id != self.id {
+                    return false;
+                }
+                if previous.is_discontinued() {
                     return false;
                 }
-                let previous_sk = previous.get_next_sk();
-                if previous_sk == self.get_current_sk() {
+                // attachment evolution keeps keys, anchoring attachments to previous state.
+                // Attachments may be left out, e.g. on interaction, but those present must be anchored.
+                let attachment_evolution = previous.get_current_sk() == self.get_current_sk()
+                    && previous.get_next_sk() == self.get_next_sk()
+                    && self.proof_of_attachments.is_some()
+                    && self.attachments.as_ref().is_none_or(|attachments| {
+                        attachments.iter().all(|attachment| attachment.verify(self))
+                    });
+                if previous.get_next_sk() == self.get_current_sk() || attachment_evolution {
                     if verify_sig(self) {
-                        previous.verify()
+                        previous.verify_with(legacy_id)
                     } else {
                         false
                     }
@@ -24,32 +46,54 @@ impl Iml {
                     false
                 }
             }
-            None => {
+            Ok(None) => {
                 let id = self.get_id();
-                let generated = hex::encode(self.get_interacion_key());
-                id == generated && verify_sig(self)
+                (id == Self::derive_id(self.get_current_sk())
+                    || legacy_id && id == hex::encode(self.get_interacion_key()))
+                    && verify_sig(self)
             }
+            Err(_) => false,
         }
     }
+
+    /// Identifier of given initial signing public key
+    pub fn derive_id(initial_sk: impl AsRef<[u8]>) -> String {
+        hex::encode(blake3::hash(initial_sk.as_ref()).as_bytes())
+    }
     /// Diffie-Hellman shared secret generator
     pub fn diffie_hellman(
         &self,
         wallet: &UnlockedWallet,
         their: impl AsRef<[u8]>,
-    ) -> Result<Vec<u8>, Error> {
-        wallet.diffie_hellman(&key_id_generate(self.get_interacion_key()), their)
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        wallet.diffie_hellman_as(
+            &key_id_generate(self.active_interaction_key()),
+            their,
+            self.get_interaction_key_type(),
+        )
     }
 }
 
-fn verify_sig(iml: &Iml) -> bool {
-    if let Ok(vk1) = VerifyingKey::from_sec1_bytes(iml.get_current_sk()) {
-        let vk = VerifyingKey::from(vk1);
-        if let Ok(sig) = Signature::from_slice(&iml.proof()) {
-            vk.verify(&iml.as_verifiable(), &sig).is_ok()
-        } else {
-            false
-        }
-    } else {
-        false
+impl Attachment {
+    /// Verifies attachment is signed by `current_sk` of holding `Iml`
+    ///  and anchored to it's previous state.
+    pub fn verify(&self, holder: &Iml) -> bool {
+        let parent = holder
+            .previous()
+            .ok()
+            .flatten()
+            .map(|previous| previous.proof())
+            .unwrap_or_default();
+        self.parent == parent
+            && self.proof.as_ref().is_some_and(|proof| {
+                holder
+                    .get_sk_type()
+                    .verify(holder.get_current_sk(), &self.as_verifiable(), proof)
+            })
     }
 }
+
+fn verify_sig(iml: &Iml) -> bool {
+    iml.get_sk_type()
+        .verify(iml.get_current_sk(), &iml.as_verifiable(), &iml.proof())
+}
@@ -0,0 +1,234 @@
+use crate::{
+    error::Error,
+    packer::Unpacked,
+    wallet::{unix_now, UnlockedWallet},
+    Iml,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, time::Duration};
+
+/// Protocol label of freshness signature
+const FRESHNESS_LABEL: &[u8] = b"did:iml/freshness";
+
+/// Size of random freshness nonce
+const NONCE_SIZE: usize = 16;
+
+/// Default [FreshnessPolicy::max_age] - five minutes
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// Signed proof that interaction `Iml` was packed just now.
+/// Present only in interaction `Iml`s and excluded from their `proof`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Freshness {
+    /// Unix time (seconds) of packing
+    timestamp: u64,
+    /// Random value unique per interaction
+    nonce: Vec<u8>,
+    /// Challenge supplied by recipient, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    challenge: Option<Vec<u8>>,
+    /// Signature of the rest with `current_sk` of carrying `Iml`
+    proof: Vec<u8>,
+}
+
+/// Content signed by [Freshness::proof], bound to carrying `Iml` and it's chain identifier
+///  and to recipient's interaction key, so it can not be relayed to another peer
+#[derive(Serialize)]
+struct FreshnessContent<'a> {
+    id: &'a str,
+    civilization: u64,
+    recipient: &'a [u8],
+    timestamp: u64,
+    nonce: &'a [u8],
+    challenge: &'a Option<Vec<u8>>,
+}
+
+impl Freshness {
+    /// Stamps `iml` as packed now for `recipient` interaction key,
+    ///  signing with it's current signing key
+    pub(crate) fn new(
+        iml: &Iml,
+        wallet: &UnlockedWallet,
+        challenge: Option<Vec<u8>>,
+        recipient: &[u8],
+    ) -> Result<Self, Error> {
+        let mut nonce = vec![0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+        let mut freshness = Self {
+            timestamp: unix_now(),
+            nonce,
+            challenge,
+            proof: vec![],
+        };
+        freshness.proof = wallet.sign_as(
+            freshness.signed_content(iml, recipient)?,
+            &iml.controller(),
+            iml.get_sk_type(),
+        )?;
+        Ok(freshness)
+    }
+
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn get_nonce(&self) -> &[u8] {
+        &self.nonce
+    }
+
+    pub fn get_challenge(&self) -> Option<&[u8]> {
+        self.challenge.as_deref()
+    }
+
+    /// Verifies signature against `current_sk` of carrying `iml`,
+    ///  which was received with our `recipient` interaction key
+    pub fn verify(&self, iml: &Iml, recipient: impl AsRef<[u8]>) -> bool {
+        self.signed_content(iml, recipient.as_ref())
+            .is_ok_and(|content| {
+                iml.get_sk_type()
+                    .verify(iml.get_current_sk(), &content, &self.proof)
+            })
+    }
+
+    fn signed_content(&self, iml: &Iml, recipient: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut content = FRESHNESS_LABEL.to_vec();
+        content.extend(serde_cbor::to_vec(&FreshnessContent {
+            id: &iml.get_id(),
+            civilization: iml.get_civilization(),
+            recipient,
+            timestamp: self.timestamp,
+            nonce: &self.nonce,
+            challenge: &self.challenge,
+        })?);
+        Ok(content)
+    }
+}
+
+/// Requirements to [Freshness] of received interaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreshnessPolicy {
+    /// Maximum difference between freshness timestamp and local clock, both ways
+    pub max_age: Duration,
+    /// Challenge previously supplied to the sender, which must be signed back
+    pub challenge: Option<Vec<u8>>,
+}
+
+impl Default for FreshnessPolicy {
+    fn default() -> Self {
+        Self {
+            max_age: DEFAULT_MAX_AGE,
+            challenge: None,
+        }
+    }
+}
+
+/// Storage of [Freshness] nonces already seen by recipient
+pub trait ReplayCache {
+    /// Remembers `nonce` seen with freshness `timestamp`.
+    /// Returns `false` if `nonce` was seen already.
+    fn insert(&mut self, nonce: &[u8], timestamp: u64) -> Result<bool, Error>;
+}
+
+/// In memory [ReplayCache].
+/// Nonces older than `window` are forgotten, so `window` should be
+///  at least twice the [FreshnessPolicy::max_age] used.
+pub struct MemoryReplayCache {
+    seen: HashMap<Vec<u8>, u64>,
+    window: Duration,
+}
+
+impl MemoryReplayCache {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            seen: HashMap::new(),
+            window,
+        }
+    }
+}
+
+impl Default for MemoryReplayCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_AGE * 2)
+    }
+}
+
+impl ReplayCache for MemoryReplayCache {
+    fn insert(&mut self, nonce: &[u8], timestamp: u64) -> Result<bool, Error> {
+        let oldest = unix_now().saturating_sub(self.window.as_secs());
+        self.seen.retain(|_, seen_at| *seen_at >= oldest);
+        Ok(self.seen.insert(nonce.to_vec(), timestamp).is_none())
+    }
+}
+
+impl Iml {
+    /// Unpacks interaction `did:iml` (see [Iml::from_did_any]) accepting it only once
+    ///  and only if it's chain verifies and it's [Freshness] satisfies `policy`.
+    ///
+    /// # Parameters
+    ///
+    /// * `did` - received interaction `did:iml`
+    /// * `wallet` - Keywault with our interaction keys
+    /// * `cache` - nonces seen so far, updated on success
+    /// * `policy` - freshness requirements
+    ///
+    pub fn from_did_fresh(
+        did: impl AsRef<str>,
+        wallet: &UnlockedWallet,
+        cache: &mut impl ReplayCache,
+        policy: &FreshnessPolicy,
+    ) -> Result<Self, Error> {
+        Self::unpack(did, wallet)?.accept_fresh(cache, policy)
+    }
+
+    /// Decrypts interaction COSE_Encrypt (see [Iml::from_cose]) accepting it only once
+    ///  and only if it's chain verifies and it's [Freshness] satisfies `policy`.
+    ///
+    /// # Parameters
+    ///
+    /// * `cose` - received interaction COSE_Encrypt
+    /// * `wallet` - Keywault with our interaction keys
+    /// * `cache` - nonces seen so far, updated on success
+    /// * `policy` - freshness requirements
+    ///
+    pub fn from_cose_fresh(
+        cose: &[u8],
+        wallet: &UnlockedWallet,
+        cache: &mut impl ReplayCache,
+        policy: &FreshnessPolicy,
+    ) -> Result<Self, Error> {
+        Self::open_cose(cose, wallet)?.accept_fresh(cache, policy)
+    }
+}
+
+impl Unpacked {
+    /// Unpacked `Iml` if it verifies and it's [Freshness] satisfies `policy` and was not seen before
+    fn accept_fresh(
+        self,
+        cache: &mut impl ReplayCache,
+        policy: &FreshnessPolicy,
+    ) -> Result<Iml, Error> {
+        let Unpacked {
+            iml, recipient_key, ..
+        } = self;
+        // freshness is signed by claimed keys, which are trusted only after verification
+        if !iml.verify() {
+            return Err(Error::VerificationFailed);
+        }
+        let freshness = iml.get_freshness().ok_or(Error::NotFresh)?;
+        let recipient = recipient_key.ok_or(Error::NotFresh)?;
+        let now = unix_now();
+        let max_age = policy.max_age.as_secs();
+        if !freshness.verify(&iml, recipient)
+            || freshness.timestamp.saturating_add(max_age) < now
+            || freshness.timestamp > now.saturating_add(max_age)
+            || policy.challenge.is_some() && freshness.challenge != policy.challenge
+        {
+            return Err(Error::NotFresh);
+        }
+        if !cache.insert(&freshness.nonce, freshness.timestamp)? {
+            return Err(Error::Replayed);
+        }
+        Ok(iml)
+    }
+}
@@ -0,0 +1,316 @@
+use crate::{
+    did_url::{percent_decode, DidUrl},
+    document::DID_IML_PREFIX,
+    error::Error,
+    Attachment, Iml,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Media type of resolved DID Document
+pub const DID_JSON_CONTENT_TYPE: &str = "application/did+ld+json";
+
+/// Media type of [ResolutionResult] served by Universal Resolver drivers
+pub const DID_RESOLUTION_CONTENT_TYPE: &str =
+    "application/ld+json;profile=\"https://w3id.org/did-resolution\"";
+
+/// JSON-LD context of [ResolutionResult] served by Universal Resolver drivers
+pub const DID_RESOLUTION_CONTEXT: &str = "https://w3id.org/did-resolution/v1";
+
+/// Path of Universal Resolver driver resolution endpoint, followed by DID
+pub const DRIVER_PATH: &str = "/1.0/identifiers/";
+
+/// DID resolution error codes
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ResolutionError {
+    /// DID is not a valid `did:iml:<id>`
+    InvalidDid,
+    /// DID URL is malformed or has invalid query
+    InvalidDidUrl,
+    /// DID of other method
+    MethodNotSupported,
+    /// No verified `Iml` is known for the DID or it has no selected resource
+    NotFound,
+    /// Identifier was discontinued
+    Deactivated,
+    /// DID Document could not be built
+    InternalError,
+}
+
+/// `didResolutionMetadata` of [ResolutionResult]
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolutionMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ResolutionError>,
+}
+
+/// `didDocumentMetadata` of [ResolutionResult]
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentMetadata {
+    /// `civilization` of resolved `Iml`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>,
+    /// Set if identifier was discontinued
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deactivated: Option<bool>,
+}
+
+/// Result of DID resolution as defined by DID Core
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolutionResult {
+    pub did_resolution_metadata: ResolutionMetadata,
+    pub did_document: Option<Value>,
+    pub did_document_metadata: DocumentMetadata,
+}
+
+impl DocumentMetadata {
+    fn of(iml: &Iml) -> Self {
+        Self {
+            version_id: Some(iml.get_civilization().to_string()),
+            deactivated: iml.is_discontinued().then_some(true),
+        }
+    }
+}
+
+/// Resource selected by DID URL
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resource {
+    /// Whole DID Document
+    Document(Value),
+    /// DID Document node selected by fragment, e.g. verification method or service
+    Node(Value),
+    /// Attachment selected by `payloadType`
+    Attachment(Attachment),
+}
+
+/// Result of DID URL dereferencing
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DereferencingResult {
+    pub dereferencing_metadata: ResolutionMetadata,
+    pub content: Option<Resource>,
+    /// Metadata of DID Document version content was selected from
+    pub content_metadata: DocumentMetadata,
+}
+
+impl DereferencingResult {
+    fn error(error: ResolutionError) -> Self {
+        Self {
+            dereferencing_metadata: ResolutionMetadata {
+                content_type: None,
+                error: Some(error),
+            },
+            ..Self::default()
+        }
+    }
+}
+
+impl ResolutionResult {
+    /// HTTP status of Universal Resolver driver response
+    pub fn http_status(&self) -> u16 {
+        match self.did_resolution_metadata.error {
+            None => 200,
+            Some(ResolutionError::InvalidDid | ResolutionError::InvalidDidUrl) => 400,
+            Some(ResolutionError::NotFound) => 404,
+            Some(ResolutionError::Deactivated) => 410,
+            Some(ResolutionError::MethodNotSupported) => 501,
+            Some(ResolutionError::InternalError) => 500,
+        }
+    }
+
+    /// Universal Resolver driver response body - this result with JSON-LD context
+    pub fn to_driver_json(&self) -> Value {
+        let mut json = serde_json::json!({ "@context": DID_RESOLUTION_CONTEXT });
+        if let (Value::Object(json), Ok(Value::Object(result))) =
+            (&mut json, serde_json::to_value(self))
+        {
+            json.extend(result);
+        }
+        json
+    }
+
+    fn error(error: ResolutionError) -> Self {
+        Self {
+            did_resolution_metadata: ResolutionMetadata {
+                content_type: None,
+                error: Some(error),
+            },
+            ..Self::default()
+        }
+    }
+}
+
+/// Resolver of `did:iml:<id>` DIDs.
+/// `did:iml` is not anchored anywhere, so only identifiers which `Iml`s
+///  were received before (or our own) can be resolved.
+#[derive(Debug, Clone, Default)]
+pub struct Resolver {
+    known: HashMap<String, Iml>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remembers verified `iml` as latest known state of it's identifier.
+    /// Interaction [Freshness](crate::replay::Freshness) is dropped.
+    ///
+    /// # Returns
+    /// - `false` if same or higher civilization is known already
+    pub fn insert(&mut self, mut iml: Iml) -> Result<bool, Error> {
+        // every state must claim identifier of verified inception
+        if !iml.verify() || iml.id != iml.get_id() {
+            return Err(Error::VerificationFailed);
+        }
+        if self
+            .known
+            .get(&iml.id)
+            .is_some_and(|known| known.get_civilization() >= iml.get_civilization())
+        {
+            return Ok(false);
+        }
+        iml.take_freshness();
+        self.known.insert(iml.id.clone(), iml);
+        Ok(true)
+    }
+
+    /// Latest known state of identifier
+    pub fn get(&self, id: &str) -> Option<&Iml> {
+        self.known.get(id)
+    }
+
+    /// Resolves `did:iml:<id>` into it's DID Document with metadata.
+    /// Discontinued identifier's last document is returned along with `deactivated` error.
+    pub fn resolve(&self, did: impl AsRef<str>) -> ResolutionResult {
+        let did = did.as_ref();
+        let Some(id) = did.strip_prefix(DID_IML_PREFIX) else {
+            return ResolutionResult::error(if did.starts_with("did:") {
+                ResolutionError::MethodNotSupported
+            } else {
+                ResolutionError::InvalidDid
+            });
+        };
+        if id.is_empty() || hex::decode(id).is_err() {
+            return ResolutionResult::error(ResolutionError::InvalidDid);
+        }
+        let Some(iml) = self.known.get(id) else {
+            return ResolutionResult::error(ResolutionError::NotFound);
+        };
+        let Ok(document) = iml.to_did_document() else {
+            return ResolutionResult::error(ResolutionError::InternalError);
+        };
+        ResolutionResult {
+            did_resolution_metadata: ResolutionMetadata {
+                content_type: Some(DID_JSON_CONTENT_TYPE.to_string()),
+                error: iml
+                    .is_discontinued()
+                    .then_some(ResolutionError::Deactivated),
+            },
+            did_document: Some(document),
+            did_document_metadata: DocumentMetadata::of(iml),
+        }
+    }
+
+    /// Resolves DID of Universal Resolver driver request path - `/1.0/identifiers/{did}`,
+    ///  where DID may be percent encoded. Query of request is ignored.
+    ///
+    /// # Returns
+    /// - `None` if path is not of resolution endpoint
+    pub fn resolve_driver_path(&self, path: &str) -> Option<ResolutionResult> {
+        let did = path.split('?').next()?.strip_prefix(DRIVER_PATH)?;
+        Some(match percent_decode(did) {
+            Ok(did) => self.resolve(did),
+            Err(_) => ResolutionResult::error(ResolutionError::InvalidDid),
+        })
+    }
+
+    /// Dereferences DID URL into selected resource:
+    /// - `versionId` query selects historical civilization, latest is used otherwise;
+    /// - `payloadType` query selects latest valid attachment of given `payload_type`;
+    /// - fragment selects DID Document node with matching `id`, e.g. verification method;
+    /// - without both - whole DID Document.
+    ///
+    /// `did:iml` defines no path resources.
+    pub fn dereference(&self, url: impl AsRef<str>) -> DereferencingResult {
+        let url = url.as_ref();
+        let Ok(url) = url.parse::<DidUrl>() else {
+            return DereferencingResult::error(
+                if url.starts_with("did:") && !url.starts_with(DID_IML_PREFIX) {
+                    ResolutionError::MethodNotSupported
+                } else {
+                    ResolutionError::InvalidDidUrl
+                },
+            );
+        };
+        let Ok(version) = url.version_id() else {
+            return DereferencingResult::error(ResolutionError::InvalidDidUrl);
+        };
+        let iml = match (self.known.get(url.id()), version) {
+            (Some(latest), Some(version)) => latest.at_civilization(version),
+            (latest, None) => latest.cloned(),
+            (None, _) => None,
+        };
+        let Some(iml) = iml.filter(|_| url.path().is_empty()) else {
+            return DereferencingResult::error(ResolutionError::NotFound);
+        };
+        let (content_type, content) = if let Some(payload_type) = url.payload_type() {
+            let attachment = iml.history().into_iter().rev().find_map(|state| {
+                state.attachments().and_then(|attachments| {
+                    attachments.into_iter().rev().find(|attachment| {
+                        attachment.get_payload_type() == payload_type && attachment.verify(&state)
+                    })
+                })
+            });
+            (
+                payload_type.to_string(),
+                attachment.map(Resource::Attachment),
+            )
+        } else {
+            let Ok(document) = iml.to_did_document() else {
+                return DereferencingResult::error(ResolutionError::InternalError);
+            };
+            let content = match url.fragment() {
+                Some(fragment) => find_node(&document, &url.did(), fragment).map(Resource::Node),
+                None => Some(Resource::Document(document)),
+            };
+            (DID_JSON_CONTENT_TYPE.to_string(), content)
+        };
+        let Some(content) = content else {
+            return DereferencingResult::error(ResolutionError::NotFound);
+        };
+        DereferencingResult {
+            dereferencing_metadata: ResolutionMetadata {
+                content_type: Some(content_type),
+                error: iml
+                    .is_discontinued()
+                    .then_some(ResolutionError::Deactivated),
+            },
+            content: Some(content),
+            content_metadata: DocumentMetadata::of(&iml),
+        }
+    }
+}
+
+/// Finds node of `document` with absolute or relative `id` of `fragment`
+fn find_node(document: &Value, did: &str, fragment: &str) -> Option<Value> {
+    let absolute = format!("{did}#{fragment}");
+    let relative = format!("#{fragment}");
+    document
+        .as_object()?
+        .values()
+        .filter_map(Value::as_array)
+        .flatten()
+        .find(|node| {
+            node.get("id")
+                .and_then(Value::as_str)
+                .is_some_and(|id| id == absolute || id == relative)
+        })
+        .cloned()
+}
@@ -0,0 +1,152 @@
+use crate::{error::Error, Attachment, Iml, UnlockedWallet};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Reserved [Attachment] `payload_type` of service endpoint changes
+pub const SERVICE_PAYLOAD_TYPE: &str = "https://www.w3.org/TR/did-core/#services";
+
+/// DID Document service endpoint
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Service {
+    /// Absolute or relative (`#<name>`) DID URL of service
+    pub id: String,
+    /// Service type, e.g. "DIDCommMessaging"
+    #[serde(rename = "type")]
+    pub service_type: String,
+    /// URL, map or set of endpoints
+    pub service_endpoint: Value,
+}
+
+impl Service {
+    pub fn new(id: impl ToString, service_type: impl ToString, endpoint: impl Into<Value>) -> Self {
+        Self {
+            id: id.to_string(),
+            service_type: service_type.to_string(),
+            service_endpoint: endpoint.into(),
+        }
+    }
+}
+
+/// Payload of [SERVICE_PAYLOAD_TYPE] attachment
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub(crate) enum ServiceChange {
+    Add { service: Service },
+    Update { service: Service },
+    Remove { id: String },
+}
+
+impl ServiceChange {
+    /// Applies change to `service` array of DID Document `fields`.
+    /// Adding existing and changing missing services is ignored.
+    pub(crate) fn apply(self, fields: &mut Map<String, Value>, did: &str) {
+        let Value::Array(services) = fields
+            .entry("service")
+            .or_insert_with(|| Value::Array(vec![]))
+        else {
+            return;
+        };
+        let position = |id: &str| {
+            services.iter().position(|service| {
+                service
+                    .get("id")
+                    .and_then(Value::as_str)
+                    .is_some_and(|existing| same_id(existing, id, did))
+            })
+        };
+        match self {
+            ServiceChange::Add { service } => {
+                if position(&service.id).is_none() {
+                    services.push(serde_json::to_value(service).unwrap());
+                }
+            }
+            ServiceChange::Update { service } => {
+                if let Some(at) = position(&service.id) {
+                    services[at] = serde_json::to_value(service).unwrap();
+                }
+            }
+            ServiceChange::Remove { id } => {
+                if let Some(at) = position(&id) {
+                    services.remove(at);
+                }
+            }
+        }
+        if services.is_empty() {
+            fields.remove("service");
+        }
+    }
+}
+
+/// Compares absolute and relative service ids
+fn same_id(a: &str, b: &str, did: &str) -> bool {
+    a.strip_prefix(did).unwrap_or(a) == b.strip_prefix(did).unwrap_or(b)
+}
+
+impl Iml {
+    /// Services of current DID Document
+    pub fn services(&self) -> Result<Vec<Service>, Error> {
+        match self.to_did_document()?.get("service") {
+            Some(services) => {
+                serde_json::from_value(services.clone()).map_err(|_| Error::MalformedDocument)
+            }
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Adds service with attachment evolution
+    pub fn add_service(self, wallet: &mut UnlockedWallet, service: Service) -> Result<Self, Error> {
+        if self.has_service(&service.id)? {
+            return Err(Error::ServiceExists);
+        }
+        self.change_services(wallet, ServiceChange::Add { service })
+    }
+
+    /// Replaces service of the same `id` with attachment evolution
+    pub fn update_service(
+        self,
+        wallet: &mut UnlockedWallet,
+        service: Service,
+    ) -> Result<Self, Error> {
+        if !self.has_service(&service.id)? {
+            return Err(Error::ServiceNotFound);
+        }
+        self.change_services(wallet, ServiceChange::Update { service })
+    }
+
+    /// Removes service with attachment evolution
+    pub fn remove_service(
+        self,
+        wallet: &mut UnlockedWallet,
+        id: impl ToString,
+    ) -> Result<Self, Error> {
+        let id = id.to_string();
+        if !self.has_service(&id)? {
+            return Err(Error::ServiceNotFound);
+        }
+        self.change_services(wallet, ServiceChange::Remove { id })
+    }
+
+    fn has_service(&self, id: &str) -> Result<bool, Error> {
+        let did = self.did();
+        Ok(self
+            .services()?
+            .iter()
+            .any(|service| same_id(&service.id, id, &did)))
+    }
+
+    fn change_services(
+        self,
+        wallet: &mut UnlockedWallet,
+        change: ServiceChange,
+    ) -> Result<Self, Error> {
+        if self.is_discontinued() {
+            return Err(Error::Discontinued);
+        }
+        let attachment = Attachment::new(
+            serde_json::to_vec(&change).map_err(|_| Error::MalformedDocument)?,
+            SERVICE_PAYLOAD_TYPE,
+        );
+        Ok(self.evolve(wallet, false, Some(vec![attachment])))
+    }
+}
@@ -0,0 +1,306 @@
+use crate::{
+    error::Error,
+    wallet::{KdfCache, KeyId, LockFormat, LockedWallet, UnlockedWallet},
+};
+use std::{
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Persistent storage of [LockedWallet] content.
+/// Content is split into records, each sealed separately,
+///  so single key can be saved without rewriting entire wallet.
+pub trait KeyStore {
+    /// Lists records present in the store
+    fn records(&self) -> Result<Vec<StoreRecord>, Error>;
+    /// Loads locked content of single record
+    fn load(&self, record: &StoreRecord) -> Result<Option<LockedWallet>, Error>;
+    /// Inserts or atomically replaces locked content of single record
+    fn save(&mut self, record: &StoreRecord, locked: &LockedWallet) -> Result<(), Error>;
+    /// Removes record if present
+    fn remove(&mut self, record: &StoreRecord) -> Result<(), Error>;
+}
+
+/// Identifies record within [KeyStore]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StoreRecord {
+    /// Wallet wide settings, such as retention policy
+    Meta,
+    /// Single key, or it's retired remains
+    Key(KeyId),
+}
+
+const META_RECORD: &str = "meta";
+
+impl Display for StoreRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreRecord::Meta => f.write_str(META_RECORD),
+            StoreRecord::Key(id) => f.write_str(&hex::encode(id)),
+        }
+    }
+}
+
+impl FromStr for StoreRecord {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == META_RECORD {
+            Ok(StoreRecord::Meta)
+        } else {
+            let mut id = KeyId::default();
+            hex::decode_to_slice(s, &mut id)?;
+            Ok(StoreRecord::Key(id))
+        }
+    }
+}
+
+impl UnlockedWallet {
+    /// Restores wallet from all records of the store.
+    /// Key is derived once per distinct salt and KDF parameters, not once per record.
+    /// # Parameters
+    /// - `store` - [KeyStore] to load records from
+    /// - `secret` - secret phrase records were locked with
+    /// # Returns
+    /// - [UnlockedWallet] instance
+    pub fn load_from(store: &impl KeyStore, secret: impl AsRef<[u8]>) -> Result<Self, Error> {
+        let secret = secret.as_ref();
+        let mut cache = KdfCache::default();
+        let mut wallet = match store.load(&StoreRecord::Meta)? {
+            Some(meta) => meta.unlock_cached(secret, &mut cache)?,
+            None => UnlockedWallet::new(),
+        };
+        for record in store.records()? {
+            if let StoreRecord::Key(_) = record {
+                if let Some(locked) = store.load(&record)? {
+                    wallet.merge(locked.unlock_cached(secret, &mut cache)?);
+                }
+            }
+        }
+        Ok(wallet)
+    }
+
+    /// Saves single key record, leaving the rest of the store untouched.
+    /// Should be called for every key added, moved or retired (e.g. ones returned by `Iml::evolve_tracked`).
+    /// Record is removed if wallet has no key under `id`.
+    /// # Parameters
+    /// - `store` - [KeyStore] to save record into
+    /// - `id` - id of the key to save
+    /// - `secret` - secret phrase to lock record with
+    /// - `format` - locked content layout and key derivation
+    pub fn save_key(
+        &self,
+        store: &mut impl KeyStore,
+        id: &KeyId,
+        secret: impl AsRef<[u8]>,
+        format: LockFormat,
+    ) -> Result<(), Error> {
+        self.save_key_cached(store, id, secret.as_ref(), format, &mut KdfCache::default())
+    }
+
+    fn save_key_cached(
+        &self,
+        store: &mut impl KeyStore,
+        id: &KeyId,
+        secret: &[u8],
+        format: LockFormat,
+        cache: &mut KdfCache,
+    ) -> Result<(), Error> {
+        let record = StoreRecord::Key(*id);
+        match self.fragment(id) {
+            Some(fragment) => store.save(
+                &record,
+                &LockedWallet::new(fragment.lock_cached(secret, format, cache)?),
+            ),
+            None => store.remove(&record),
+        }
+    }
+
+    /// Saves every record of the wallet, removing stale ones.
+    /// Each record is locked separately, but under single salt, so `format` KDF runs once.
+    /// # Parameters
+    /// - `store` - [KeyStore] to save records into
+    /// - `secret` - secret phrase to lock records with
+    /// - `format` - locked content layout and key derivation
+    pub fn save_to(
+        &self,
+        store: &mut impl KeyStore,
+        secret: impl AsRef<[u8]>,
+        format: LockFormat,
+    ) -> Result<(), Error> {
+        let secret = secret.as_ref();
+        let mut cache = KdfCache::default();
+        let meta = self.settings();
+        store.save(
+            &StoreRecord::Meta,
+            &LockedWallet::new(meta.lock_cached(secret, format, &mut cache)?),
+        )?;
+        let ids = self.key_ids();
+        for id in ids.iter() {
+            self.save_key_cached(store, id, secret, format, &mut cache)?;
+        }
+        for record in store.records()? {
+            if let StoreRecord::Key(id) = record {
+                if !ids.contains(&id) {
+                    store.remove(&record)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Directory backed [KeyStore].
+/// Each record is a separate file replaced by atomic write-rename.
+/// Directory is exclusively locked for as long as store is open.
+///
+/// Replaced and removed files are unlinked, not overwritten, so their content, retired secrets included,
+///  stays on disk until the space is reused. Use `SqliteKeyStore` or encrypted storage
+///  where forward security of retired keys matters.
+pub struct FileKeyStore {
+    dir: PathBuf,
+    _lock: File,
+}
+
+const RECORD_EXTENSION: &str = "imlw";
+const LOCK_FILE: &str = ".lock";
+
+impl FileKeyStore {
+    /// Opens store in `dir`, creating it if missing.
+    /// Fails with [Error::StoreLocked] if store is already open elsewhere.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join(LOCK_FILE))?;
+        lock.try_lock().map_err(|_| Error::StoreLocked)?;
+        Ok(Self { dir, _lock: lock })
+    }
+
+    fn path_for(&self, record: &StoreRecord) -> PathBuf {
+        self.dir.join(format!("{record}.{RECORD_EXTENSION}"))
+    }
+}
+
+impl KeyStore for FileKeyStore {
+    fn records(&self) -> Result<Vec<StoreRecord>, Error> {
+        let mut records = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == RECORD_EXTENSION) {
+                if let Some(record) = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .and_then(|s| s.parse().ok())
+                {
+                    records.push(record);
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    fn load(&self, record: &StoreRecord) -> Result<Option<LockedWallet>, Error> {
+        match fs::read(self.path_for(record)) {
+            Ok(content) => Ok(Some(LockedWallet::new(content))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&mut self, record: &StoreRecord, locked: &LockedWallet) -> Result<(), Error> {
+        let path = self.path_for(record);
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(locked.content())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+        // persist rename itself, directories can not be opened as files on Windows
+        #[cfg(unix)]
+        File::open(&self.dir)?.sync_all()?;
+        Ok(())
+    }
+
+    fn remove(&mut self, record: &StoreRecord) -> Result<(), Error> {
+        match fs::remove_file(self.path_for(record)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// SQLite backed [KeyStore].
+/// Each record is a row, replaced within single statement transaction.
+#[cfg(feature = "sqlite")]
+pub struct SqliteKeyStore {
+    connection: rusqlite::Connection,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteKeyStore {
+    /// Opens store in database file at `path`, creating it if missing
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::with_connection(rusqlite::Connection::open(path)?)
+    }
+
+    /// Opens store over existing connection, creating records table if missing.
+    /// Secure delete is enabled, so replaced and removed records, retired secrets included,
+    ///  are overwritten instead of left in free pages.
+    pub fn with_connection(connection: rusqlite::Connection) -> Result<Self, Error> {
+        connection.execute_batch("PRAGMA secure_delete = ON;")?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS iml_wallet (
+                record TEXT PRIMARY KEY NOT NULL,
+                content BLOB NOT NULL
+            )",
+            (),
+        )?;
+        Ok(Self { connection })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl KeyStore for SqliteKeyStore {
+    fn records(&self) -> Result<Vec<StoreRecord>, Error> {
+        let mut statement = self.connection.prepare("SELECT record FROM iml_wallet")?;
+        let names = statement
+            .query_map((), |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(names.iter().filter_map(|n| n.parse().ok()).collect())
+    }
+
+    fn load(&self, record: &StoreRecord) -> Result<Option<LockedWallet>, Error> {
+        use rusqlite::OptionalExtension;
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT content FROM iml_wallet WHERE record = ?1",
+                [record.to_string()],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?
+            .map(LockedWallet::new))
+    }
+
+    fn save(&mut self, record: &StoreRecord, locked: &LockedWallet) -> Result<(), Error> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO iml_wallet (record, content) VALUES (?1, ?2)",
+            (record.to_string(), locked.content()),
+        )?;
+        Ok(())
+    }
+
+    fn remove(&mut self, record: &StoreRecord) -> Result<(), Error> {
+        self.connection.execute(
+            "DELETE FROM iml_wallet WHERE record = ?1",
+            [record.to_string()],
+        )?;
+        Ok(())
+    }
+}
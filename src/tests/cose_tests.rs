@@ -0,0 +1,171 @@
+use super::packer_tests::{stripped, view_for};
+use crate::{
+    error::Error,
+    key_formats::VerificationKey,
+    replay::{FreshnessPolicy, MemoryReplayCache},
+    wallet::{KeyRetention, KeyType},
+    Attachment, Iml, UnlockedWallet,
+};
+use coset::{CborSerializable, CoseEncrypt};
+
+#[test]
+fn cose_sign1_test() {
+    for sk_type in [KeyType::Ed25519_256, KeyType::Ed25519, KeyType::EcdsaP256] {
+        let mut w = UnlockedWallet::with_retention(KeyRetention::RetireSuperseded);
+        let iml = Iml::new_with(&mut w, sk_type, KeyType::EcdhP256).unwrap();
+        let sign1 = iml.sign_cose_sign1(&w).unwrap();
+        assert!(iml.verify_cose_sign1(&sign1));
+        let mut tampered = sign1.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(!iml.verify_cose_sign1(&tampered));
+        // previous states stay verifiable after evolution
+        let evolved = iml.clone().evolve(
+            &mut w,
+            true,
+            Some(vec![Attachment::new(b"payload", "text/plain")]),
+        );
+        assert!(evolved.verify_cose_sign1(&sign1));
+        assert!(evolved.verify_cose_sign1(&evolved.sign_cose_sign1(&w).unwrap()));
+        // retired key can not sign any more
+        assert!(matches!(iml.sign_cose_sign1(&w), Err(Error::KeyRetired)));
+        // attachments are signed by holding state's key
+        let attachment = &evolved.attachments().unwrap()[0];
+        let sign1 = attachment.sign_cose_sign1(&evolved, &w).unwrap();
+        assert!(attachment.verify_cose_sign1(&sign1, &evolved));
+        assert!(!attachment.verify_cose_sign1(&sign1, &iml));
+        assert!(!Attachment::new(b"other", "text/plain").verify_cose_sign1(&sign1, &evolved));
+        // signature of other identifier
+        let mut other_w = UnlockedWallet::new();
+        let other = Iml::new_with(&mut other_w, sk_type, KeyType::EcdhP256).unwrap();
+        assert!(!evolved.verify_cose_sign1(&other.sign_cose_sign1(&other_w).unwrap()));
+        let discontinued = evolved.discontinue(&mut w).unwrap();
+        assert!(matches!(
+            discontinued.sign_cose_sign1(&w),
+            Err(Error::Discontinued)
+        ));
+    }
+}
+
+#[test]
+fn cose_sign1_evolved_test() {
+    let mut w = UnlockedWallet::new();
+    let iml = Iml::new_with(&mut w, KeyType::Ed25519, KeyType::X25519)
+        .unwrap()
+        .evolve(&mut w, true, None)
+        .evolve(
+            &mut w,
+            false,
+            Some(vec![Attachment::new(b"payload", "text/plain")]),
+        )
+        .evolve(&mut w, true, None);
+    assert_eq!(3, iml.get_civilization());
+    // every state, including attachment evolution, is signed by it's own key
+    for state in iml.history() {
+        let sign1 = state.sign_cose_sign1(&w).unwrap();
+        assert!(state.verify_cose_sign1(&sign1));
+        assert!(iml.verify_cose_sign1(&sign1));
+    }
+    let with_attachment = iml.at_civilization(2).unwrap();
+    let attachment = &with_attachment.attachments().unwrap()[0];
+    let sign1 = attachment.sign_cose_sign1(&with_attachment, &w).unwrap();
+    assert!(attachment.verify_cose_sign1(&sign1, &with_attachment));
+    // previous states can not be signed after their keys are retired
+    let mut retiring = UnlockedWallet::with_retention(KeyRetention::RetireSuperseded);
+    let iml = Iml::new(&mut retiring).unwrap();
+    let evolved = iml.clone().evolve(&mut retiring, true, None);
+    assert!(matches!(
+        iml.sign_cose_sign1(&retiring),
+        Err(Error::KeyRetired)
+    ));
+    assert!(matches!(
+        evolved
+            .at_civilization(0)
+            .unwrap()
+            .sign_cose_sign1(&retiring),
+        Err(Error::KeyRetired)
+    ));
+    assert!(evolved.verify_cose_sign1(&evolved.sign_cose_sign1(&retiring).unwrap()));
+}
+
+#[test]
+fn cose_encrypt_test() {
+    let mut a_w = UnlockedWallet::new();
+    let a = Iml::new(&mut a_w).unwrap();
+    for interaction_type in [KeyType::EcdhP256, KeyType::X25519] {
+        let mut b_w = UnlockedWallet::new();
+        let b = Iml::new_with(&mut b_w, KeyType::Ed25519, interaction_type).unwrap();
+        let attachments = Some(vec![Attachment::new(b"payload", "text/plain")]);
+        let packed = a
+            .interact_cose(&mut a_w, b.as_did(None).unwrap(), attachments.clone())
+            .unwrap();
+        let received = Iml::from_cose(&packed, &b_w).unwrap();
+        let freshness = received.get_freshness().unwrap();
+        assert!(freshness.verify(&received, b.get_interacion_key()));
+        assert!(!freshness.verify(&received, a.get_interacion_key()));
+        assert_eq!(attachments, received.attachments());
+        let mut expected = view_for(&a, &a_w, &b.id);
+        expected.attachments = attachments;
+        assert_eq!(expected, stripped(received));
+        // accepted only once
+        let mut cache = MemoryReplayCache::default();
+        let policy = FreshnessPolicy::default();
+        assert!(Iml::from_cose_fresh(&packed, &b_w, &mut cache, &policy).is_ok());
+        assert!(matches!(
+            Iml::from_cose_fresh(&packed, &b_w, &mut cache, &policy),
+            Err(Error::Replayed)
+        ));
+        // not a recipient
+        assert!(Iml::from_cose(&packed, &a_w).is_err());
+        let mut tampered = CoseEncrypt::from_slice(&packed).unwrap();
+        tampered.ciphertext.as_mut().unwrap()[0] ^= 1;
+        assert!(Iml::from_cose(&tampered.to_vec().unwrap(), &b_w).is_err());
+        // key is bound to recipient's protected header bytes as received:
+        //  same header with non-preferred encoding of `alg` (-25) does not decrypt
+        let canonical = [0x44, 0xa1, 0x01, 0x38, 0x18];
+        let position = packed
+            .windows(canonical.len())
+            .position(|window| window == canonical)
+            .unwrap();
+        let mut reencoded = packed[..position].to_vec();
+        reencoded.extend([0x45, 0xa1, 0x01, 0x39, 0x00, 0x18]);
+        reencoded.extend(&packed[position + canonical.len()..]);
+        let recipient = &CoseEncrypt::from_slice(&reencoded).unwrap().recipients[0];
+        assert_eq!(
+            CoseEncrypt::from_slice(&packed).unwrap().recipients[0]
+                .protected
+                .header,
+            recipient.protected.header
+        );
+        assert!(Iml::from_cose(&reencoded, &b_w).is_err());
+    }
+    // non-Iml recipient
+    let mut peer_w = UnlockedWallet::new();
+    let id = peer_w.new_key(KeyType::X25519, None).unwrap();
+    let did_key = format!(
+        "did:key:{}",
+        VerificationKey {
+            id: String::new(),
+            key_type: KeyType::X25519,
+            public: peer_w.public_for(&id, KeyType::X25519).unwrap(),
+        }
+        .to_multikey()
+        .unwrap()
+    );
+    let packed = a.interact_cose(&mut a_w, &did_key, None).unwrap();
+    assert_eq!(
+        view_for(&a, &a_w, &did_key),
+        stripped(Iml::from_cose(&packed, &peer_w).unwrap())
+    );
+}
+
+#[test]
+fn cose_encrypt0_test() {
+    let mut w = UnlockedWallet::new();
+    let iml = Iml::new(&mut w).unwrap();
+    let key = [7u8; 32];
+    let packed = iml.to_cose_encrypt0(&key).unwrap();
+    assert_eq!(iml, Iml::from_cose_encrypt0(&packed, &key).unwrap());
+    assert!(Iml::from_cose_encrypt0(&packed, &[8u8; 32]).is_err());
+    assert!(iml.to_cose_encrypt0(&key[..16]).is_err());
+}
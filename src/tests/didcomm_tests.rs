@@ -0,0 +1,145 @@
+use crate::{error::Error, resolver::Resolver, wallet::KeyType, Iml, UnlockedWallet};
+use base64ct::{Base64UrlUnpadded, Encoding};
+use serde_json::json;
+
+#[test]
+fn didcomm_authcrypt_test() {
+    for (sk_type, interaction_type) in [
+        (KeyType::Ed25519_256, KeyType::EcdhP256),
+        (KeyType::Ed25519, KeyType::X25519),
+    ] {
+        let mut a_w = UnlockedWallet::new();
+        let mut b_w = UnlockedWallet::new();
+        let mut c_w = UnlockedWallet::new();
+        let alice = Iml::new_with(&mut a_w, sk_type, interaction_type).unwrap();
+        let bob = Iml::new_with(&mut b_w, sk_type, interaction_type).unwrap();
+        let carol = Iml::new_with(&mut c_w, sk_type, interaction_type).unwrap();
+        let message = json!({
+            "id": "1234567890",
+            "type": "https://didcomm.org/basicmessage/2.0/message",
+            "from": alice.did(),
+            "to": [bob.did(), carol.did()],
+            "body": { "content": "hello" },
+        });
+        let packed = alice.pack_didcomm(&a_w, &message, &[&bob, &carol]).unwrap();
+        let mut resolver = Resolver::new();
+        // unknown sender can not be authenticated
+        assert!(matches!(
+            bob.unpack_didcomm(&b_w, &packed, &resolver),
+            Err(Error::KeyNotFound)
+        ));
+        resolver.insert(alice.clone()).unwrap();
+        for (iml, wallet) in [(&bob, &b_w), (&carol, &c_w)] {
+            let unpacked = iml.unpack_didcomm(wallet, &packed, &resolver).unwrap();
+            assert_eq!(message, unpacked.message);
+            assert_eq!(
+                iml.interaction_verification_key().id,
+                unpacked.recipient_kid
+            );
+            assert_eq!(
+                Some(alice.interaction_verification_key().id),
+                unpacked.sender_kid
+            );
+        }
+        // not a recipient
+        assert!(matches!(
+            alice.unpack_didcomm(&a_w, &packed, &resolver),
+            Err(Error::KeyNotFound)
+        ));
+        // sender must be `from` of the message
+        let forged = alice
+            .pack_didcomm(&a_w, &json!({ "from": carol.did(), "body": {} }), &[&bob])
+            .unwrap();
+        assert!(matches!(
+            bob.unpack_didcomm(&b_w, &forged, &resolver),
+            Err(Error::MalformedMessage)
+        ));
+        // tampered ciphertext
+        let mut jwe: serde_json::Value = serde_json::from_str(&packed).unwrap();
+        jwe["ciphertext"] = json!("AAAA");
+        assert!(bob
+            .unpack_didcomm(&b_w, jwe.to_string(), &resolver)
+            .is_err());
+        // truncated tag
+        let mut jwe: serde_json::Value = serde_json::from_str(&packed).unwrap();
+        let tag = Base64UrlUnpadded::decode_vec(jwe["tag"].as_str().unwrap()).unwrap();
+        jwe["tag"] = json!(Base64UrlUnpadded::encode_string(&tag[..16]));
+        assert!(matches!(
+            bob.unpack_didcomm(&b_w, jwe.to_string(), &resolver),
+            Err(Error::MalformedMessage)
+        ));
+    }
+}
+
+#[test]
+fn didcomm_anoncrypt_test() {
+    let mut b_w = UnlockedWallet::new();
+    let bob = Iml::new(&mut b_w).unwrap();
+    let message = json!({ "id": "1", "type": "ping", "body": {} });
+    let packed = Iml::pack_didcomm_anon(&message, &[&bob]).unwrap();
+    let unpacked = bob.unpack_didcomm(&b_w, &packed, &Resolver::new()).unwrap();
+    assert_eq!(message, unpacked.message);
+    assert_eq!(None, unpacked.sender_kid);
+    // rotated interaction key still decrypts within grace period
+    let bob = bob.rotate_interaction_key(&mut b_w).unwrap();
+    assert_eq!(
+        message,
+        bob.unpack_didcomm(&b_w, &packed, &Resolver::new())
+            .unwrap()
+            .message
+    );
+    // mixed key agreement types can not share ephemeral key
+    let mut c_w = UnlockedWallet::new();
+    let carol = Iml::new_with(&mut c_w, KeyType::Ed25519, KeyType::X25519).unwrap();
+    assert!(matches!(
+        Iml::pack_didcomm_anon(&message, &[&bob, &carol]),
+        Err(Error::UnsupportedKeyType)
+    ));
+}
+
+#[test]
+fn didcomm_after_interaction_test() {
+    let mut a_w = UnlockedWallet::new();
+    let mut b_w = UnlockedWallet::new();
+    let mut c_w = UnlockedWallet::new();
+    let alice = Iml::new(&mut a_w).unwrap();
+    let bob = Iml::new(&mut b_w).unwrap();
+    let carol = Iml::new(&mut c_w).unwrap();
+    // peers know each other by pairwise views only
+    let to_bob = alice
+        .interact(&mut a_w, bob.as_did(None).unwrap(), None)
+        .unwrap();
+    let alice_view = Iml::from_did_any(&to_bob, &b_w).unwrap();
+    let to_alice = bob.interact(&mut b_w, &to_bob, None).unwrap();
+    let bob_view = Iml::from_did_any(&to_alice, &a_w).unwrap();
+    let mut a_resolver = Resolver::new();
+    a_resolver.insert(bob_view.clone()).unwrap();
+    let mut b_resolver = Resolver::new();
+    b_resolver.insert(alice_view.clone()).unwrap();
+    let message = json!({ "from": alice.did(), "to": [bob.did()], "body": {} });
+    let packed = alice.pack_didcomm(&a_w, &message, &[&bob_view]).unwrap();
+    let unpacked = bob.unpack_didcomm(&b_w, &packed, &b_resolver).unwrap();
+    assert_eq!(message, unpacked.message);
+    assert_eq!(
+        Some(alice_view.interaction_verification_key().id),
+        unpacked.sender_kid
+    );
+    assert_eq!(
+        bob_view.interaction_verification_key().id,
+        unpacked.recipient_kid
+    );
+    let reply = json!({ "from": bob.did(), "to": [alice.did()], "body": {} });
+    let packed = bob.pack_didcomm(&b_w, &reply, &[&alice_view]).unwrap();
+    assert_eq!(
+        reply,
+        alice
+            .unpack_didcomm(&a_w, &packed, &a_resolver)
+            .unwrap()
+            .message
+    );
+    // single sender key can not be both pairwise and public one
+    assert!(matches!(
+        alice.pack_didcomm(&a_w, &message, &[&bob_view, &carol]),
+        Err(Error::KeyNotFound)
+    ));
+}
@@ -0,0 +1,160 @@
+use crate::{
+    document::DID_CORE_PAYLOAD_TYPE, error::Error, service::Service, wallet::KeyType, Attachment,
+    Iml, UnlockedWallet,
+};
+
+#[test]
+fn did_document_test() {
+    let mut w = UnlockedWallet::new();
+    let iml = Iml::new_with(&mut w, KeyType::Ed25519_256, KeyType::X25519).unwrap();
+    let did = format!("did:iml:{}", iml.get_id());
+    assert_eq!(did, iml.did());
+    let document = iml.to_did_document().unwrap();
+    assert_eq!(did, document["id"]);
+    assert_eq!(format!("{did}#sk-0"), document["authentication"][0]);
+    assert_eq!(format!("{did}#sk-0"), document["assertionMethod"][0]);
+    let ik = iml.interaction_verification_key().id;
+    assert!(ik.starts_with(&format!("{did}#ik-")));
+    assert_eq!(ik, document["keyAgreement"][0]);
+    let methods = document["verificationMethod"].as_array().unwrap();
+    // multicodec prefixes of compressed secp256k1 and X25519 keys
+    assert!(methods[0]["publicKeyMultibase"]
+        .as_str()
+        .unwrap()
+        .starts_with("zQ3s"));
+    assert!(methods[1]["publicKeyMultibase"]
+        .as_str()
+        .unwrap()
+        .starts_with("z6LS"));
+
+    let service = br##"{"id":"did:iml:other","service":[{"id":"#inbox","type":"DIDCommMessaging","serviceEndpoint":"https://example.com"}]}"##;
+    let iml = iml.evolve(
+        &mut w,
+        true,
+        Some(vec![
+            Attachment::new(service.to_vec(), DID_CORE_PAYLOAD_TYPE),
+            Attachment::new(b"not a document".to_vec(), "text/plain"),
+        ]),
+    );
+    assert!(iml.verify());
+    assert!(iml
+        .attachments()
+        .unwrap()
+        .iter()
+        .all(|attachment| attachment.verify(&iml)));
+    let document = iml.to_did_document().unwrap();
+    // id is protected
+    assert_eq!(did, document["id"]);
+    assert_eq!("#inbox", document["service"][0]["id"]);
+    assert_eq!(format!("{did}#sk-1"), document["authentication"][0]);
+    // interaction key was not rotated
+    assert_eq!(ik, document["keyAgreement"][0]);
+    // attached content survives further evolutions
+    let document = iml.evolve(&mut w, true, None).to_did_document().unwrap();
+    assert_eq!("#inbox", document["service"][0]["id"]);
+    assert_eq!(format!("{did}#sk-2"), document["authentication"][0]);
+}
+
+#[test]
+fn service_endpoints_test() {
+    let mut w = UnlockedWallet::new();
+    let iml = Iml::new(&mut w).unwrap();
+    let inbox = Service::new("#inbox", "DIDCommMessaging", "https://example.com/inbox");
+    let iml = iml.add_service(&mut w, inbox.clone()).unwrap();
+    assert_eq!(1, iml.get_civilization());
+    assert!(iml.verify());
+    assert!(matches!(
+        iml.clone().add_service(&mut w, inbox.clone()),
+        Err(Error::ServiceExists)
+    ));
+    // attachment evolution keeps signing keys
+    assert_eq!(
+        iml.previous().unwrap().unwrap().get_current_sk(),
+        iml.get_current_sk()
+    );
+    let relay = Service::new(
+        format!("{}#relay", iml.did()),
+        "LinkedDomains",
+        serde_json::json!({ "origins": ["https://example.org"] }),
+    );
+    let iml = iml.add_service(&mut w, relay.clone()).unwrap();
+    // key evolution in between
+    let iml = iml.evolve(&mut w, true, None);
+    let moved = Service::new("#inbox", "DIDCommMessaging", "https://example.net/inbox");
+    let iml = iml.update_service(&mut w, moved.clone()).unwrap();
+    assert!(iml.verify());
+    assert_eq!(vec![moved, relay.clone()], iml.services().unwrap());
+    let document = iml.to_did_document().unwrap();
+    assert_eq!(
+        "https://example.net/inbox",
+        document["service"][0]["serviceEndpoint"]
+    );
+
+    // absolute id matches relative one
+    let inbox_id = format!("{}#inbox", iml.did());
+    let iml = iml.remove_service(&mut w, inbox_id).unwrap();
+    assert_eq!(5, iml.get_civilization());
+    assert_eq!(vec![relay], iml.services().unwrap());
+    assert!(matches!(
+        iml.clone().remove_service(&mut w, "#inbox"),
+        Err(Error::ServiceNotFound)
+    ));
+    let iml = iml.remove_service(&mut w, "#relay").unwrap();
+    assert!(iml.verify());
+    assert!(iml.services().unwrap().is_empty());
+    assert!(iml.to_did_document().unwrap().get("service").is_none());
+    // still signs with right key after attachment evolutions
+    let iml = iml.evolve(&mut w, true, None);
+    assert!(iml.verify());
+    let discontinued = iml.discontinue(&mut w).unwrap();
+    assert!(discontinued.verify());
+    assert!(matches!(
+        discontinued.add_service(&mut w, inbox),
+        Err(Error::Discontinued)
+    ));
+}
+
+#[test]
+fn re_evolve_attachment_evolutions_test() {
+    let mut w = UnlockedWallet::new();
+    let inbox = Service::new("#inbox", "DIDCommMessaging", "https://example.com/inbox");
+    let relay = Service::new("#relay", "LinkedDomains", "https://example.org");
+    let with_inbox = Iml::new(&mut w)
+        .unwrap()
+        .add_service(&mut w, inbox)
+        .unwrap();
+    let with_relay = with_inbox
+        .clone()
+        .evolve(&mut w, true, None)
+        .add_service(&mut w, relay)
+        .unwrap();
+    let iml = with_relay.clone().evolve(
+        &mut w,
+        true,
+        Some(vec![Attachment::new(b"payload".to_vec(), "text/plain")]),
+    );
+    assert_eq!(4, iml.get_civilization());
+    let attachments = iml
+        .history()
+        .into_iter()
+        .filter_map(|state| state.attachments())
+        .flatten()
+        .collect::<Vec<_>>();
+    assert_eq!(3, attachments.len());
+    let restored = Iml::re_evolve(&w, iml.get_id(), Some(attachments));
+    assert_eq!(iml, restored);
+    assert!(restored.verify());
+    // without attachments only key evolutions are known
+    assert!(Iml::re_evolve(&w, iml.get_id(), None).get_civilization() < 4);
+
+    // attachments of attachment evolution must be anchored to previous state
+    let mut stripped = with_relay.clone();
+    stripped.attachments = None;
+    assert!(stripped.verify());
+    let mut unanchored = with_relay.clone();
+    unanchored.attachments = with_inbox.attachments();
+    assert!(!unanchored.verify());
+    let mut unsigned = with_relay;
+    unsigned.attachments = Some(vec![Attachment::new(b"forged".to_vec(), "text/plain")]);
+    assert!(!unsigned.verify());
+}
@@ -1,2 +1,7 @@
+mod cose_tests;
+mod didcomm_tests;
+mod document_tests;
 mod packer_tests;
+mod resolver_tests;
+mod store_tests;
 mod wallet_tests;
@@ -1,4 +1,25 @@
-use crate::{wallet::UnlockedWallet, Iml};
+use crate::{
+    envelope::{Cipher, Envelope, EnvelopeHeader, Mode},
+    error::Error,
+    wallet::{key_id_generate, KeyType, UnlockedWallet},
+    Attachment, Iml, InteractOptions,
+};
+
+/// Received Iml without interaction only data
+pub(crate) fn stripped(mut iml: Iml) -> Iml {
+    assert!(iml.take_freshness().is_some());
+    iml
+}
+
+/// View of `sender` packed for `peer` with it's current pairwise key
+pub(crate) fn view_for(sender: &Iml, wallet: &UnlockedWallet, peer: &str) -> Iml {
+    let pairwise = wallet
+        .pairwise_key_for(peer, sender.get_interaction_key_type())
+        .unwrap();
+    let view = sender.pairwise_view(wallet, &pairwise).unwrap();
+    assert_ne!(sender.get_interacion_key(), view.get_interacion_key());
+    view
+}
 
 #[test]
 fn instantiation_test() {
@@ -13,6 +34,669 @@ fn interact_implemented_test() {
     let mut w = UnlockedWallet::new();
     Iml::new(&mut w)
         .unwrap()
-        .interact(&w, "0x1234", None)
+        .interact(&mut w, "0x1234", None)
+        .unwrap();
+}
+
+#[test]
+fn signing_key_types_test() {
+    for sk_type in [KeyType::Ed25519_256, KeyType::Ed25519, KeyType::EcdsaP256] {
+        let mut w = UnlockedWallet::new();
+        let iml = Iml::new_with(&mut w, sk_type, KeyType::EcdhP256).unwrap();
+        assert_eq!(sk_type, iml.get_sk_type());
+        assert!(iml.verify());
+        let iml = iml.evolve(&mut w, true, None).evolve(&mut w, true, None);
+        assert!(iml.verify());
+        assert_eq!(iml, Iml::re_evolve(&w, iml.get_id(), None));
+        let inflated = Iml::inflate(iml.deflate(None).unwrap().0, None, None).unwrap();
+        assert_eq!(sk_type, inflated.get_sk_type());
+    }
+    let mut w = UnlockedWallet::new();
+    assert!(Iml::new_with(&mut w, KeyType::EcdhP256, KeyType::EcdhP256).is_err());
+    assert!(Iml::new_with(&mut w, KeyType::Ed25519, KeyType::Ed25519).is_err());
+}
+
+#[test]
+fn interaction_key_rotation_test() {
+    let mut a_wallet = UnlockedWallet::new();
+    let mut b_wallet = UnlockedWallet::new();
+    let a = Iml::new(&mut a_wallet).unwrap();
+    let b = Iml::new(&mut b_wallet).unwrap();
+    let b_did = b.as_did(None).unwrap();
+    let rotated = b.clone().rotate_interaction_key(&mut b_wallet).unwrap();
+    assert!(rotated.verify());
+    assert_eq!(b.get_id(), rotated.get_id());
+    assert_ne!(b.get_interacion_key(), rotated.get_interacion_key());
+    // evolution keeps rotated key
+    let evolved = rotated.clone().evolve(&mut b_wallet, true, None);
+    assert_eq!(rotated.get_interacion_key(), evolved.get_interacion_key());
+    assert_eq!(evolved, Iml::re_evolve(&b_wallet, b.get_id(), None));
+    // peer still using old key within grace period
+    let stale = a.interact(&mut a_wallet, &b_did, None).unwrap();
+    assert_eq!(
+        view_for(&a, &a_wallet, &b.id),
+        stripped(Iml::from_did_any(&stale, &b_wallet).unwrap())
+    );
+    let fresh = a
+        .interact(&mut a_wallet, evolved.as_did(None).unwrap(), None)
+        .unwrap();
+    assert_eq!(
+        view_for(&a, &a_wallet, &b.id),
+        stripped(Iml::from_did_any(&fresh, &b_wallet).unwrap())
+    );
+    // grace is over
+    b_wallet.set_interaction_grace(std::time::Duration::ZERO);
+    assert!(Iml::from_did_any(&stale, &b_wallet).is_err());
+    assert_eq!(
+        vec![crate::wallet::key_id_generate(b.get_interacion_key())],
+        b_wallet.purge_expired_keys()
+    );
+    assert_eq!(
+        view_for(&a, &a_wallet, &b.id),
+        stripped(Iml::from_did_any(&fresh, &b_wallet).unwrap())
+    );
+    // rotation is re-evolved from public remains
+    assert_eq!(evolved, Iml::re_evolve(&b_wallet, b.get_id(), None));
+}
+
+#[test]
+fn identifier_derivation_test() {
+    let mut w = UnlockedWallet::new();
+    let iml = Iml::new(&mut w).unwrap();
+    assert_eq!(Iml::derive_id(iml.get_current_sk()), iml.get_id());
+    assert_ne!(hex::encode(iml.get_interacion_key()), iml.get_id());
+    assert!(iml.verify());
+    let evolved = iml.evolve(&mut w, true, None);
+    assert_eq!(evolved, Iml::re_evolve(&w, evolved.get_id(), None));
+    assert!(evolved.verify());
+    // id is carried unchanged by following civilizations
+    let mut forged = evolved.clone();
+    forged.id = Iml::derive_id(forged.get_next_sk());
+    forged.proof = Some(
+        w.sign_as(
+            forged.as_verifiable(),
+            &crate::wallet::key_id_generate("sk_1"),
+            KeyType::Ed25519_256,
+        )
+        .unwrap(),
+    );
+    assert!(!forged.verify());
+    assert_ne!(forged.id, forged.get_id());
+    // id is bound to initial signing key
+    let mut forged = Iml::new(&mut w).unwrap();
+    forged.id = evolved.get_id();
+    forged.proof = Some(
+        w.sign_as(
+            forged.as_verifiable(),
+            &crate::wallet::key_id_generate("sk_0"),
+            KeyType::Ed25519_256,
+        )
+        .unwrap(),
+    );
+    assert!(!forged.verify());
+}
+
+#[test]
+fn legacy_identifier_compatibility_test() {
+    let mut w = UnlockedWallet::new();
+    let mut legacy = Iml::new(&mut w).unwrap();
+    // as created by older versions
+    legacy.id = hex::encode(legacy.get_interacion_key());
+    legacy.proof = Some(
+        w.sign_as(
+            legacy.as_verifiable(),
+            &crate::wallet::key_id_generate("sk_0"),
+            KeyType::Ed25519_256,
+        )
+        .unwrap(),
+    );
+    assert!(!legacy.verify());
+    assert!(legacy.verify_with(true));
+    let evolved = legacy.evolve(&mut w, true, None);
+    assert!(!evolved.verify());
+    assert!(evolved.verify_with(true));
+    assert_eq!(evolved, Iml::re_evolve(&w, evolved.get_id(), None));
+}
+
+#[test]
+fn replay_protection_test() {
+    use crate::{
+        error::Error,
+        replay::{FreshnessPolicy, MemoryReplayCache},
+        InteractOptions,
+    };
+    let mut a_wallet = UnlockedWallet::new();
+    let mut b_wallet = UnlockedWallet::new();
+    let a = Iml::new(&mut a_wallet).unwrap();
+    let b = Iml::new(&mut b_wallet).unwrap();
+    let b_did = b.as_did(None).unwrap();
+    let mut cache = MemoryReplayCache::default();
+    let policy = FreshnessPolicy::default();
+    let packed = a.interact(&mut a_wallet, &b_did, None).unwrap();
+    let received = Iml::from_did_fresh(&packed, &b_wallet, &mut cache, &policy).unwrap();
+    assert!(received
+        .get_freshness()
+        .unwrap()
+        .verify(&received, b.get_interacion_key()));
+    assert!(received.verify());
+    assert_eq!(view_for(&a, &a_wallet, &b.id), stripped(received));
+    // same interaction again
+    assert!(matches!(
+        Iml::from_did_fresh(&packed, &b_wallet, &mut cache, &policy),
+        Err(Error::Replayed)
+    ));
+    // new one is accepted
+    let packed = a.interact(&mut a_wallet, &b_did, None).unwrap();
+    assert!(Iml::from_did_fresh(&packed, &b_wallet, &mut cache, &policy).is_ok());
+    // challenge must be signed back
+    let challenged = FreshnessPolicy {
+        challenge: Some(b"challenge".to_vec()),
+        ..FreshnessPolicy::default()
+    };
+    let packed = a.interact(&mut a_wallet, &b_did, None).unwrap();
+    assert!(matches!(
+        Iml::from_did_fresh(&packed, &b_wallet, &mut cache, &challenged),
+        Err(Error::NotFresh)
+    ));
+    let options = InteractOptions {
+        challenge: challenged.challenge.clone(),
+        ..InteractOptions::default()
+    };
+    let packed = a
+        .interact_with(&mut a_wallet, &b_did, None, options)
+        .unwrap();
+    assert!(Iml::from_did_fresh(&packed, &b_wallet, &mut cache, &challenged).is_ok());
+    // no freshness in plain did
+    assert!(matches!(
+        Iml::from_did_fresh(a.as_did(None).unwrap(), &b_wallet, &mut cache, &policy),
+        Err(Error::NotFresh)
+    ));
+    // anoncrypt interaction can not be relayed to another peer
+    let mut m_wallet = UnlockedWallet::new();
+    let m = Iml::new(&mut m_wallet).unwrap();
+    let anoncrypt = InteractOptions {
+        mode: Mode::Anoncrypt,
+        ..InteractOptions::default()
+    };
+    let packed = a
+        .interact_with(&mut a_wallet, m.as_did(None).unwrap(), None, anoncrypt)
+        .unwrap();
+    let relayed = Iml::from_did_any(&packed, &m_wallet).unwrap();
+    let b_key = b.get_interacion_key();
+    let mut header = EnvelopeHeader::current(KeyType::EcdhP256, Cipher::default(), Mode::Anoncrypt);
+    header.recipient = Some(key_id_generate(&b_key));
+    let (ephemeral, dx) = KeyType::EcdhP256.ephemeral_agreement(&b_key).unwrap();
+    let key = header.derive_key(dx, &ephemeral, &b_key).unwrap();
+    let (payload, nonce) = relayed.deflate(Some((key, Cipher::default()))).unwrap();
+    let relayed = Envelope {
+        sender_key: ephemeral,
+        payload: &payload,
+        nonce: &nonce,
+        header,
+    }
+    .format()
+    .unwrap();
+    assert!(Iml::from_did_any(&relayed, &b_wallet).unwrap().verify());
+    assert!(matches!(
+        Iml::from_did_fresh(&relayed, &b_wallet, &mut cache, &policy),
+        Err(Error::NotFresh)
+    ));
+    // freshness of unverifiable Iml is not trusted
+    let mut forged = a.clone();
+    forged.id = b.get_id();
+    let packed = forged.interact(&mut a_wallet, &b_did, None).unwrap();
+    assert!(matches!(
+        Iml::from_did_fresh(&packed, &b_wallet, &mut cache, &policy),
+        Err(Error::VerificationFailed)
+    ));
+}
+
+#[test]
+fn non_iml_recipient_test() {
+    use crate::{error::Error, key_formats::VerificationKey, Cipher, InteractOptions, Mode};
+    let multikey = |wallet: &mut UnlockedWallet, key_type| {
+        let id = wallet.new_key(key_type, None).unwrap();
+        VerificationKey {
+            id: String::new(),
+            key_type,
+            public: wallet.public_for(&id, key_type).unwrap(),
+        }
+        .to_multikey()
+        .unwrap()
+    };
+    let mut a_wallet = UnlockedWallet::new();
+    let a = Iml::new_with(&mut a_wallet, KeyType::Ed25519, KeyType::X25519).unwrap();
+    let mut b_wallet = UnlockedWallet::new();
+    let a_p256 = Iml::new(&mut b_wallet).unwrap();
+    let mut peer_wallet = UnlockedWallet::new();
+    let did_key = format!("did:key:{}", multikey(&mut peer_wallet, KeyType::X25519));
+    let did_peer = format!(
+        "did:peer:2.V{}.E{}.SeyJ0IjoiZG0iLCJzIjoiaHR0cHM6Ly9leGFtcGxlLmNvbSJ9",
+        multikey(&mut peer_wallet, KeyType::Ed25519),
+        multikey(&mut peer_wallet, KeyType::EcdhP256)
+    );
+    // authcrypt to did:key
+    let packed = a.interact(&mut a_wallet, &did_key, None).unwrap();
+    let received = Iml::from_did_any(&packed, &peer_wallet).unwrap();
+    assert!(received.verify());
+    assert_eq!(view_for(&a, &a_wallet, &did_key), stripped(received));
+    // same pairwise key is used for the same peer
+    let pairwise = a_wallet.pairwise_key_for(&did_key, KeyType::X25519);
+    assert!(pairwise.is_some());
+    a.interact(&mut a_wallet, &did_key, None).unwrap();
+    assert_eq!(
+        pairwise,
+        a_wallet.pairwise_key_for(&did_key, KeyType::X25519)
+    );
+    // authcrypt to did:peer:2 key agreement key
+    let packed = a_p256.interact(&mut b_wallet, &did_peer, None).unwrap();
+    assert_eq!(
+        view_for(&a_p256, &b_wallet, &did_peer),
+        stripped(Iml::from_did_any(&packed, &peer_wallet).unwrap())
+    );
+    // anoncrypt to any key agreement type
+    let options = InteractOptions {
+        cipher: Cipher::XChaCha20Poly1305,
+        mode: Mode::Anoncrypt,
+        ..InteractOptions::default()
+    };
+    let packed = a_p256
+        .interact_with(&mut b_wallet, &did_key, None, options)
         .unwrap();
+    assert_eq!(
+        view_for(&a_p256, &b_wallet, &did_key),
+        stripped(Iml::from_did_any(&packed, &peer_wallet).unwrap())
+    );
+    // Ed25519 did:key is converted to X25519
+    let ed25519 = format!("did:key:{}", multikey(&mut peer_wallet, KeyType::Ed25519));
+    assert!(a.interact(&mut a_wallet, &ed25519, None).is_ok());
+    // key types must match for authcrypt
+    assert!(matches!(
+        a_p256.interact(&mut b_wallet, &did_key, None),
+        Err(Error::UnsupportedKeyType)
+    ));
+    // did:peer:2 without key agreement key
+    assert!(matches!(
+        a.interact(
+            &mut a_wallet,
+            "did:peer:2.Vz6MkqRYqQiSgvZQdnBytw86Qbs2ZWUkGv22od935YF4s8M7V",
+            None
+        ),
+        Err(Error::KeyNotFound)
+    ));
+    assert!(a.interact(&mut a_wallet, "did:key:zinvalid", None).is_err());
+}
+
+#[test]
+fn data_signature_test() {
+    use crate::{data_signature::DataSignature, error::Error, Attachment};
+    for sk_type in [KeyType::Ed25519_256, KeyType::Ed25519, KeyType::EcdsaP256] {
+        let mut w = UnlockedWallet::new();
+        let iml = Iml::new_with(&mut w, sk_type, KeyType::X25519).unwrap();
+        let data = b"GET /api/resource";
+        let signed_0 = iml.sign_data(&w, data).unwrap();
+        assert_eq!(0, signed_0.get_civilization());
+        assert!(iml.verify_data(data, &signed_0));
+        assert!(!iml.verify_data(b"GET /api/other", &signed_0));
+        // serialized envelope
+        let signed_0: DataSignature =
+            serde_cbor::from_slice(&serde_cbor::to_vec(&signed_0).unwrap()).unwrap();
+        let evolved = iml.clone().evolve(&mut w, true, None);
+        let signed_1 = evolved.sign_data(&w, data).unwrap();
+        assert_eq!(1, signed_1.get_civilization());
+        // older key remains valid for it's civilization
+        assert!(evolved.verify_data(data, &signed_0));
+        assert!(evolved.verify_data(data, &signed_1));
+        // civilization unknown to verifier
+        assert!(!iml.verify_data(data, &signed_1));
+        // attachment evolution keeps signing key
+        let attached =
+            evolved
+                .clone()
+                .evolve(&mut w, false, Some(vec![Attachment::new(b"{}", "test")]));
+        let signed_2 = attached.sign_data(&w, data).unwrap();
+        assert!(attached.verify_data(data, &signed_1));
+        assert!(attached.verify_data(data, &signed_2));
+        // signatures of other identifier are rejected
+        let mut other_w = UnlockedWallet::new();
+        let other = Iml::new_with(&mut other_w, sk_type, KeyType::X25519).unwrap();
+        assert!(!other.verify_data(data, &signed_0));
+        let mut impostor_w = UnlockedWallet::new();
+        Iml::new_with(&mut impostor_w, sk_type, KeyType::X25519).unwrap();
+        let forged = other.sign_data(&impostor_w, data).unwrap();
+        assert!(!other.verify_data(data, &forged));
+        assert!(other.verify_data(data, &other.sign_data(&other_w, data).unwrap()));
+        // discontinued identifier signs nothing, but old signatures stay valid
+        let discontinued = attached.discontinue(&mut w).unwrap();
+        assert!(matches!(
+            discontinued.sign_data(&w, data),
+            Err(Error::Discontinued)
+        ));
+        assert!(discontinued.verify_data(data, &signed_2));
+    }
+}
+
+#[test]
+fn interact_derived_key_test() -> Result<(), Error> {
+    let mut a_wallet = UnlockedWallet::new();
+    let mut b_wallet = UnlockedWallet::new();
+    let a = Iml::new(&mut a_wallet)?;
+    let b = Iml::new(&mut b_wallet)?;
+    let b_id = key_id_generate(b.get_interacion_key());
+    let packed = a.interact(&mut a_wallet, b.as_did(None)?, None)?;
+    let envelope = Envelope::parse(&packed)?;
+    assert_eq!(crate::envelope::ENVELOPE_VERSION, envelope.header.version);
+    assert_eq!(
+        view_for(&a, &a_wallet, &b.id),
+        stripped(Iml::from_did(&packed, &b_wallet, b_id)?)
+    );
+    // raw shared secret is not an encryption key anymore
+    let raw = b.diffie_hellman(&b_wallet, a.get_interacion_key())?;
+    let nonce = hex::decode(envelope.nonce)?;
+    assert!(Iml::inflate(
+        envelope.payload,
+        Some((raw.clone(), Cipher::default())),
+        Some(&nonce)
+    )
+    .is_err());
+    // key is bound to both parties and direction
+    let ab = envelope.header.derive_key(
+        raw.clone(),
+        &a.get_interacion_key(),
+        &b.get_interacion_key(),
+    )?;
+    let ba = envelope
+        .header
+        .derive_key(raw, &b.get_interacion_key(), &a.get_interacion_key())?;
+    assert_ne!(ab, ba);
+    Ok(())
+}
+
+#[test]
+fn interact_cipher_agility_test() -> Result<(), Error> {
+    let mut a_wallet = UnlockedWallet::new();
+    let mut b_wallet = UnlockedWallet::new();
+    let a = Iml::new(&mut a_wallet)?;
+    let b = Iml::new_with(&mut b_wallet, KeyType::Ed25519_256, KeyType::EcdhP256)?;
+    let b_id = key_id_generate(b.get_interacion_key());
+    for (cipher, nonce_size) in [
+        (Cipher::XSalsa20Poly1305, 24),
+        (Cipher::XChaCha20Poly1305, 24),
+        (Cipher::Aes256Gcm, 12),
+    ] {
+        let options = InteractOptions {
+            cipher,
+            ..InteractOptions::default()
+        };
+        let packed = a.interact_with(&mut a_wallet, b.as_did(None)?, None, options)?;
+        let envelope = Envelope::parse(&packed)?;
+        assert_eq!(cipher, envelope.header.cipher);
+        assert_eq!(nonce_size * 2, envelope.nonce.len());
+        assert_eq!(
+            view_for(&a, &a_wallet, &b.id),
+            stripped(Iml::from_did(&packed, &b_wallet, b_id)?)
+        );
+        // cipher substitution is rejected
+        let forged = Envelope {
+            header: EnvelopeHeader {
+                cipher: Cipher::XChaCha20Poly1305,
+                ..envelope.header.clone()
+            },
+            ..envelope
+        }
+        .format()?;
+        if cipher != Cipher::XChaCha20Poly1305 {
+            assert!(Iml::from_did(forged, &b_wallet, b_id).is_err());
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn interact_anoncrypt_test() -> Result<(), Error> {
+    let mut a_wallet = UnlockedWallet::new();
+    let mut b_wallet = UnlockedWallet::new();
+    let a = Iml::new(&mut a_wallet)?;
+    // recipient's key agreement is used
+    let b = Iml::new_with(&mut b_wallet, KeyType::Ed25519_256, KeyType::X25519)?;
+    let b_did = b.as_did(None)?;
+    let b_id = key_id_generate(b.get_interacion_key());
+    let anoncrypt = InteractOptions {
+        mode: Mode::Anoncrypt,
+        ..InteractOptions::default()
+    };
+    let first = a.interact_with(&mut a_wallet, &b_did, None, anoncrypt.clone())?;
+    let second = a.interact_with(&mut a_wallet, &b_did, None, anoncrypt.clone())?;
+    let (first_env, second_env) = (Envelope::parse(&first)?, Envelope::parse(&second)?);
+    assert_eq!(Mode::Anoncrypt, first_env.header.mode);
+    assert_eq!(KeyType::X25519, first_env.header.key_type);
+    // unlinkable to sender and each other
+    assert_ne!(a.get_interacion_key(), first_env.sender_key);
+    assert_ne!(first_env.sender_key, second_env.sender_key);
+    assert_eq!(
+        view_for(&a, &a_wallet, &b.id),
+        stripped(Iml::from_did(&first, &b_wallet, b_id)?)
+    );
+    assert_eq!(
+        view_for(&a, &a_wallet, &b.id),
+        stripped(Iml::from_did(&second, &b_wallet, b_id)?)
+    );
+    // mode is bound to derived key
+    let forged = Envelope {
+        header: EnvelopeHeader {
+            mode: Mode::Authcrypt,
+            ..first_env.header.clone()
+        },
+        ..first_env
+    }
+    .format()?;
+    assert!(Iml::from_did(forged, &b_wallet, b_id).is_err());
+    Ok(())
+}
+
+#[test]
+fn from_did_any_test() -> Result<(), Error> {
+    let mut a_wallet = UnlockedWallet::new();
+    let mut b_wallet = UnlockedWallet::new();
+    let a = Iml::new(&mut a_wallet)?;
+    // several interaction keys, as after rotation
+    let b_old = Iml::new(&mut b_wallet)?;
+    let b = Iml::new(&mut b_wallet)?;
+    b_wallet.new_key(KeyType::X25519, None)?;
+    for b in [&b_old, &b] {
+        let packed = a.interact(&mut a_wallet, b.as_did(None)?, None)?;
+        let envelope = Envelope::parse(&packed)?;
+        assert_eq!(
+            Some(key_id_generate(b.get_interacion_key())),
+            envelope.header.recipient
+        );
+        assert_eq!(
+            view_for(&a, &a_wallet, &b.id),
+            stripped(Iml::from_did_any(&packed, &b_wallet)?)
+        );
+        // trial decryption of legacy envelope without reference
+        let legacy = a.as_did(Some(a.diffie_hellman(&a_wallet, b.get_interacion_key())?))?;
+        assert_eq!(None, Envelope::parse(&legacy)?.header.recipient);
+        assert_eq!(a, Iml::from_did_any(legacy, &b_wallet)?);
+        // legacy envelope must be sent by owner of carried Iml
+        let mut m_wallet = UnlockedWallet::new();
+        let m = Iml::new(&mut m_wallet)?;
+        let dh = m.diffie_hellman(&m_wallet, b.get_interacion_key())?;
+        let m_did = m.as_did(Some(dh.clone()))?;
+        let (payload, nonce) = a.deflate(Some((dh, Cipher::default())))?;
+        let forged = Envelope {
+            payload: &payload,
+            nonce: &nonce,
+            ..Envelope::parse(&m_did)?
+        }
+        .format()?;
+        assert!(Iml::from_did_any(&m_did, &b_wallet).is_ok());
+        assert!(Iml::from_did_any(forged, &b_wallet).is_err());
+    }
+    assert!(Iml::from_did_any(
+        a.interact(
+            &mut a_wallet,
+            Iml::new(&mut UnlockedWallet::new())?.as_did(None)?,
+            None
+        )?,
+        &b_wallet
+    )
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn pairwise_interaction_keys_test() -> Result<(), Error> {
+    let mut a_wallet = UnlockedWallet::new();
+    let mut b_wallet = UnlockedWallet::new();
+    let mut c_wallet = UnlockedWallet::new();
+    let a = Iml::new(&mut a_wallet)?;
+    let b = Iml::new(&mut b_wallet)?;
+    let c = Iml::new(&mut c_wallet)?;
+    let to_b = a.interact(&mut a_wallet, b.as_did(None)?, None)?;
+    let to_b_again = a.interact(&mut a_wallet, b.as_did(None)?, None)?;
+    let to_c = a.interact(&mut a_wallet, c.as_did(None)?, None)?;
+    let b_pairwise = Envelope::parse(&to_b)?.sender_key;
+    // remembered per peer, never the public one
+    assert_eq!(b_pairwise, Envelope::parse(&to_b_again)?.sender_key);
+    assert_ne!(b_pairwise, Envelope::parse(&to_c)?.sender_key);
+    assert_ne!(a.get_interacion_key(), b_pairwise);
+    assert_eq!(
+        Some(key_id_generate(&b_pairwise)),
+        a_wallet.pairwise_key_for(&b.id, KeyType::EcdhP256)
+    );
+    assert_eq!(
+        view_for(&a, &a_wallet, &b.id),
+        stripped(Iml::from_did_any(&to_b, &b_wallet)?)
+    );
+    assert_eq!(
+        view_for(&a, &a_wallet, &c.id),
+        stripped(Iml::from_did_any(&to_c, &c_wallet)?)
+    );
+    // payload carries only the peer's own key material
+    let b_view = Iml::from_did_any(&to_b, &b_wallet)?;
+    let c_view = Iml::from_did_any(&to_c, &c_wallet)?;
+    assert_eq!(b_pairwise, b_view.get_interacion_key());
+    assert_ne!(b_view.get_interacion_key(), c_view.get_interacion_key());
+    assert_ne!(b_view.proof(), c_view.proof());
+    assert!(b_view.verify() && c_view.verify());
+    let anoncrypt = InteractOptions {
+        mode: Mode::Anoncrypt,
+        ..InteractOptions::default()
+    };
+    let anon_to_c = a.interact_with(&mut a_wallet, c.as_did(None)?, None, anoncrypt)?;
+    assert_eq!(
+        c_view.get_interacion_key(),
+        Iml::from_did_any(&anon_to_c, &c_wallet)?.get_interacion_key()
+    );
+    // reply goes to pairwise key
+    let reply = b.interact(&mut b_wallet, &to_b, None)?;
+    assert_eq!(
+        Some(key_id_generate(&b_pairwise)),
+        Envelope::parse(&reply)?.header.recipient
+    );
+    assert_eq!(
+        view_for(&b, &b_wallet, &a.id),
+        stripped(Iml::from_did_any(&reply, &a_wallet)?)
+    );
+    // pairwise key must be attested by sender
+    let mut envelope = Envelope::parse(&to_b)?;
+    envelope.header.pairwise_proof = Some(vec![0u8; 64]);
+    assert!(Iml::from_did_any(envelope.format()?, &b_wallet).is_err());
+    // sender key of plain envelope is not attested, so replies go to the carried key
+    let (payload, nonce) = a.deflate(None)?;
+    let forged = Envelope {
+        sender_key: c.interaction_key.clone(),
+        payload: &payload,
+        nonce: &nonce,
+        header: EnvelopeHeader::current(KeyType::EcdhP256, Cipher::default(), Mode::Authcrypt),
+    }
+    .format()?;
+    let reply = b.interact(&mut b_wallet, &forged, None)?;
+    assert!(Iml::from_did_any(&reply, &c_wallet).is_err());
+    assert_eq!(b.id, Iml::from_did_any(&reply, &a_wallet)?.id);
+    // unverifiable peers are not interacted with
+    let mut forged = a.clone();
+    forged.id = c.id.clone();
+    assert!(matches!(
+        b.interact(&mut b_wallet, forged.as_did(None)?, None),
+        Err(Error::VerificationFailed)
+    ));
+    Ok(())
+}
+
+#[test]
+fn evolved_interaction_test() -> Result<(), Error> {
+    let mut a_wallet = UnlockedWallet::new();
+    let mut b_wallet = UnlockedWallet::new();
+    let a = Iml::new(&mut a_wallet)?;
+    let b = Iml::new(&mut b_wallet)?;
+    let b_did = b.as_did(None)?;
+    let before = a.interact(&mut a_wallet, &b_did, None)?;
+    let evolved = a.clone().evolve(
+        &mut a_wallet,
+        true,
+        Some(vec![Attachment::new("data", "text")]),
+    );
+    let packed = evolved.interact(&mut a_wallet, &b_did, evolved.attachments())?;
+    let received = Iml::from_did_any(&packed, &b_wallet)?;
+    assert!(received.verify());
+    assert_eq!(a.id, received.get_id());
+    assert_eq!(
+        view_for(&evolved, &a_wallet, &b.id),
+        stripped(received.clone())
+    );
+    // pairwise key is kept over evolution, but earlier states carry public key
+    assert_eq!(
+        Envelope::parse(&before)?.sender_key,
+        received.get_interacion_key()
+    );
+    assert_eq!(
+        a.get_interacion_key(),
+        received.previous()?.unwrap().get_interacion_key()
+    );
+    // pairwise keys rotate together with interaction key
+    let rotated = evolved.rotate_interaction_key(&mut a_wallet)?;
+    let packed = rotated.interact(&mut a_wallet, &b_did, None)?;
+    let received = Iml::from_did_any(&packed, &b_wallet)?;
+    assert!(received.verify());
+    assert_eq!(
+        view_for(&rotated, &a_wallet, &b.id),
+        stripped(received.clone())
+    );
+    assert_ne!(
+        Envelope::parse(&before)?.sender_key,
+        received.get_interacion_key()
+    );
+    // superseded pairwise key is usable within grace period only
+    let reply = b.interact(&mut b_wallet, &before, None)?;
+    assert!(Iml::from_did_any(&reply, &a_wallet).is_ok());
+    a_wallet.set_interaction_grace(std::time::Duration::ZERO);
+    assert!(a_wallet
+        .purge_expired_keys()
+        .contains(&key_id_generate(Envelope::parse(&before)?.sender_key)));
+    assert!(Iml::from_did_any(&reply, &a_wallet).is_err());
+    Ok(())
+}
+
+#[test]
+fn interact_x25519_test() -> Result<(), Error> {
+    let mut a_wallet = UnlockedWallet::new();
+    let mut b_wallet = UnlockedWallet::new();
+    let a = Iml::new_with(&mut a_wallet, KeyType::Ed25519_256, KeyType::X25519)?;
+    let b = Iml::new_with(&mut b_wallet, KeyType::Ed25519_256, KeyType::X25519)?;
+    assert_eq!(KeyType::X25519, a.get_interaction_key_type());
+    let a_did = a.as_did(Some(a.diffie_hellman(&a_wallet, b.get_interacion_key())?))?;
+    let b_did = b.as_did(Some(b.diffie_hellman(&b_wallet, a.get_interacion_key())?))?;
+    let packed = a.interact(&mut a_wallet, b_did, None)?;
+    let unpacked = Iml::from_did(packed, &b_wallet, key_id_generate(b.get_interacion_key()))?;
+    assert_eq!(view_for(&a, &a_wallet, &b.id), stripped(unpacked.clone()));
+    assert!(unpacked.verify());
+    assert!(b.interact(&mut b_wallet, a_did, None).is_ok());
+    // mixed key agreement types are rejected
+    let mut c_wallet = UnlockedWallet::new();
+    let c = Iml::new(&mut c_wallet)?;
+    let c_did = c.as_did(None)?;
+    assert!(a.interact(&mut a_wallet, c_did, None).is_err());
+    Ok(())
 }
@@ -0,0 +1,228 @@
+use crate::{
+    did_url::DidUrl,
+    document::DID_CORE_PAYLOAD_TYPE,
+    error::Error,
+    resolver::{
+        ResolutionError, Resolver, Resource, DID_JSON_CONTENT_TYPE, DID_RESOLUTION_CONTEXT,
+    },
+    Attachment, Iml, UnlockedWallet,
+};
+
+#[test]
+fn resolution_test() {
+    let mut w = UnlockedWallet::new();
+    let iml = Iml::new(&mut w).unwrap();
+    let mut resolver = Resolver::new();
+    for (did, error) in [
+        ("iml", ResolutionError::InvalidDid),
+        ("did:iml:", ResolutionError::InvalidDid),
+        ("did:iml:not-hex", ResolutionError::InvalidDid),
+        ("did:key:z6Mk", ResolutionError::MethodNotSupported),
+        (iml.did().as_str(), ResolutionError::NotFound),
+    ] {
+        let result = resolver.resolve(did);
+        assert_eq!(Some(error), result.did_resolution_metadata.error);
+        assert!(result.did_document.is_none());
+    }
+    assert!(resolver.insert(iml.clone()).unwrap());
+    let result = resolver.resolve(iml.did());
+    assert_eq!(None, result.did_resolution_metadata.error);
+    assert_eq!(
+        Some(DID_JSON_CONTENT_TYPE),
+        result.did_resolution_metadata.content_type.as_deref()
+    );
+    assert_eq!(iml.to_did_document().ok(), result.did_document);
+    assert_eq!(
+        Some("0"),
+        result.did_document_metadata.version_id.as_deref()
+    );
+
+    let evolved = iml.clone().evolve(&mut w, true, None);
+    assert!(resolver.insert(evolved.clone()).unwrap());
+    // older civilization does not replace newer one
+    assert!(!resolver.insert(iml).unwrap());
+    let result = serde_json::to_value(resolver.resolve(evolved.did())).unwrap();
+    assert_eq!("1", result["didDocumentMetadata"]["versionId"]);
+    assert!(result["didDocumentMetadata"].get("deactivated").is_none());
+
+    let mut forged = evolved.clone().evolve(&mut w, true, None);
+    forged.id = "00".into();
+    assert!(matches!(
+        resolver.insert(forged),
+        Err(Error::VerificationFailed)
+    ));
+    // malformed inversion is rejected, not panicked on
+    let mut malformed = evolved.clone();
+    malformed.inversion = Some("not-deflated".into());
+    assert!(malformed.previous().is_err());
+    assert!(matches!(
+        resolver.insert(malformed),
+        Err(Error::VerificationFailed)
+    ));
+
+    let discontinued = evolved.discontinue(&mut w).unwrap();
+    assert!(discontinued.verify());
+    assert!(discontinued.is_discontinued());
+    assert!(matches!(
+        discontinued.clone().discontinue(&mut w),
+        Err(Error::Discontinued)
+    ));
+    // no further evolution
+    assert_eq!(
+        discontinued,
+        discontinued.clone().evolve(&mut w, true, None)
+    );
+    resolver.insert(discontinued.clone()).unwrap();
+    let result = serde_json::to_value(resolver.resolve(discontinued.did())).unwrap();
+    assert_eq!("deactivated", result["didResolutionMetadata"]["error"]);
+    assert_eq!(true, result["didDocumentMetadata"]["deactivated"]);
+    assert_eq!("2", result["didDocumentMetadata"]["versionId"]);
+    assert!(result["didDocument"].is_object());
+}
+
+#[test]
+fn did_url_parsing_test() {
+    let url: DidUrl = "did:iml:00ff/some/path?versionId=2&payloadType=text%2Fplain#sk-1"
+        .parse()
+        .unwrap();
+    assert_eq!("00ff", url.id());
+    assert_eq!("did:iml:00ff", url.did());
+    assert_eq!("/some/path", url.path());
+    assert_eq!(Some(2), url.version_id().unwrap());
+    assert_eq!(Some("text/plain"), url.payload_type());
+    assert_eq!(Some("sk-1"), url.fragment());
+    assert_eq!(url, url.to_string().parse().unwrap());
+    let url: DidUrl = "did:iml:00ff?versionId=latest".parse().unwrap();
+    assert!(url.version_id().is_err());
+    for invalid in [
+        "iml:00ff",
+        "did:key:z6Mk#key-1",
+        "did:iml:not-hex#key-1",
+        "did:iml:00ff#%zz",
+        "did:iml:ab:cd:ef",
+    ] {
+        assert!(invalid.parse::<DidUrl>().is_err(), "{invalid}");
+    }
+}
+
+#[test]
+fn dereferencing_test() {
+    let mut w = UnlockedWallet::new();
+    let iml = Iml::new(&mut w).unwrap();
+    let did = iml.did();
+    let service =
+        br##"{"service":[{"id":"#inbox","type":"DIDCommMessaging","serviceEndpoint":"https://example.com"}]}"##;
+    let evolved = iml.evolve(
+        &mut w,
+        true,
+        Some(vec![
+            Attachment::new(service.to_vec(), DID_CORE_PAYLOAD_TYPE),
+            Attachment::new(b"hello".to_vec(), "text/plain"),
+        ]),
+    );
+    let evolved = evolved.evolve(&mut w, true, None);
+    let mut resolver = Resolver::new();
+    resolver.insert(evolved.clone()).unwrap();
+
+    let result = resolver.dereference(&did);
+    assert_eq!(
+        Some(Resource::Document(evolved.to_did_document().unwrap())),
+        result.content
+    );
+    assert_eq!(Some("2"), result.content_metadata.version_id.as_deref());
+
+    // historical key by absolute id
+    let result = resolver.dereference(format!("{did}?versionId=0#sk-0"));
+    let Some(Resource::Node(method)) = result.content else {
+        panic!("verification method expected")
+    };
+    assert_eq!(format!("{did}#sk-0"), method["id"]);
+    assert_eq!(Some("0"), result.content_metadata.version_id.as_deref());
+    // superseded key is not in latest document
+    assert_eq!(
+        Some(ResolutionError::NotFound),
+        resolver
+            .dereference(format!("{did}#sk-0"))
+            .dereferencing_metadata
+            .error
+    );
+    // service by relative id
+    let Some(Resource::Node(inbox)) = resolver.dereference(format!("{did}#inbox")).content else {
+        panic!("service expected")
+    };
+    assert_eq!("https://example.com", inbox["serviceEndpoint"]);
+
+    let result = resolver.dereference(format!("{did}?payloadType=text%2Fplain"));
+    assert_eq!(
+        Some("text/plain"),
+        result.dereferencing_metadata.content_type.as_deref()
+    );
+    let Some(Resource::Attachment(attachment)) = result.content else {
+        panic!("attachment expected")
+    };
+    assert_eq!(b"hello", attachment.get_payload());
+    // attachment is not present before it was attached
+    assert!(resolver
+        .dereference(format!("{did}?versionId=0&payloadType=text%2Fplain"))
+        .content
+        .is_none());
+
+    for (url, error) in [
+        (format!("{did}?versionId=3"), ResolutionError::NotFound),
+        (format!("{did}/path"), ResolutionError::NotFound),
+        (format!("{did}?versionId=x"), ResolutionError::InvalidDidUrl),
+        ("did:iml:zz".to_string(), ResolutionError::InvalidDidUrl),
+        (
+            "did:web:example.com".to_string(),
+            ResolutionError::MethodNotSupported,
+        ),
+    ] {
+        assert_eq!(
+            Some(error),
+            resolver.dereference(&url).dereferencing_metadata.error,
+            "{url}"
+        );
+    }
+    assert_eq!(
+        Some(DID_JSON_CONTENT_TYPE),
+        resolver
+            .dereference(&did)
+            .dereferencing_metadata
+            .content_type
+            .as_deref()
+    );
+}
+
+#[test]
+fn driver_path_resolution_test() {
+    let mut w = UnlockedWallet::new();
+    let iml = Iml::new(&mut w).unwrap();
+    let mut resolver = Resolver::new();
+    resolver.insert(iml.clone()).unwrap();
+    assert!(resolver.resolve_driver_path("/1.0/other").is_none());
+    let result = resolver
+        .resolve_driver_path(&format!("/1.0/identifiers/did%3Aiml%3A{}", iml.get_id()))
+        .unwrap();
+    assert_eq!(200, result.http_status());
+    let json = result.to_driver_json();
+    assert_eq!(DID_RESOLUTION_CONTEXT, json["@context"]);
+    assert_eq!(iml.did(), json["didDocument"]["id"]);
+    assert_eq!("0", json["didDocumentMetadata"]["versionId"]);
+    for (path, status) in [
+        ("/1.0/identifiers/did:iml:00?versionId=1".to_string(), 404),
+        ("/1.0/identifiers/did:iml:%zz".to_string(), 400),
+        ("/1.0/identifiers/did:key:z6Mk".to_string(), 501),
+    ] {
+        let result = resolver.resolve_driver_path(&path).unwrap();
+        assert_eq!(status, result.http_status(), "{path}");
+        assert!(result.to_driver_json()["didDocument"].is_null());
+    }
+    let path = format!("/1.0/identifiers/{}", iml.did());
+    resolver.insert(iml.discontinue(&mut w).unwrap()).unwrap();
+    let result = resolver.resolve_driver_path(&path).unwrap();
+    assert_eq!(410, result.http_status());
+    assert_eq!(
+        true,
+        result.to_driver_json()["didDocumentMetadata"]["deactivated"]
+    );
+}
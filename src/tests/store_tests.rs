@@ -0,0 +1,90 @@
+use crypto_secretbox::{KeyInit, XSalsa20Poly1305};
+use rand::{rngs::OsRng, RngCore};
+
+use crate::{
+    error::Error,
+    store::{FileKeyStore, KeyStore, StoreRecord},
+    wallet::{key_id_generate, KdfParams, KeyRetention, LockFormat},
+    Iml, UnlockedWallet,
+};
+
+fn temp_store_dir() -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("iml_store_{}", OsRng.next_u64()))
+}
+
+/// Saves initial wallet, then only records touched by evolution
+fn incremental_roundtrip(store: &mut impl KeyStore) {
+    let pass = XSalsa20Poly1305::generate_key(&mut OsRng);
+    let mut w = UnlockedWallet::with_retention(KeyRetention::RetireSuperseded);
+    let iml = Iml::new(&mut w).unwrap();
+    w.save_to(store, pass, LockFormat::Legacy).unwrap();
+    let records = store.records().unwrap().len();
+    let (iml, changed) = iml.evolve_tracked(&mut w, true, None);
+    // sk_2 got generated and sk_0 retired
+    assert_eq!(
+        vec![key_id_generate("sk_2"), key_id_generate("sk_0")],
+        changed
+    );
+    for touched in &changed {
+        w.save_key(store, touched, pass, LockFormat::Legacy)
+            .unwrap();
+    }
+    assert_eq!(records + 1, store.records().unwrap().len());
+    let loaded = UnlockedWallet::load_from(store, pass).unwrap();
+    assert_eq!(loaded, w);
+    assert_eq!(iml, Iml::re_evolve(&loaded, iml.get_id(), None));
+    // stale records are cleaned by full save
+    let fresh = UnlockedWallet::new();
+    fresh.save_to(store, pass, LockFormat::Legacy).unwrap();
+    assert_eq!(vec![StoreRecord::Meta], store.records().unwrap());
+}
+
+#[test]
+fn file_key_store_test() {
+    let dir = temp_store_dir();
+    let mut store = FileKeyStore::open(&dir).unwrap();
+    incremental_roundtrip(&mut store);
+    assert!(matches!(FileKeyStore::open(&dir), Err(Error::StoreLocked)));
+    drop(store);
+    assert!(FileKeyStore::open(&dir).is_ok());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_key_store_test() {
+    let connection = rusqlite::Connection::open_in_memory().unwrap();
+    let mut store = crate::store::SqliteKeyStore::with_connection(connection).unwrap();
+    incremental_roundtrip(&mut store);
+}
+
+#[test]
+fn single_derivation_store_test() {
+    let dir = temp_store_dir();
+    let mut store = FileKeyStore::open(&dir).unwrap();
+    let format = LockFormat::V1(KdfParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    });
+    let mut w = UnlockedWallet::new();
+    let _ = Iml::new(&mut w).unwrap();
+    w.save_to(&mut store, b"secret", format).unwrap();
+    // all records share single header, so key is derived once per save and load
+    let headers: std::collections::HashSet<Vec<u8>> = store
+        .records()
+        .unwrap()
+        .iter()
+        .map(|record| store.load(record).unwrap().unwrap().content()[..33].to_vec())
+        .collect();
+    assert_eq!(1, headers.len());
+    assert_eq!(w, UnlockedWallet::load_from(&store, b"secret").unwrap());
+    // separately saved key gets it's own salt and still loads
+    let id = key_id_generate("extra");
+    w.new_key_for(id).unwrap();
+    w.save_key(&mut store, &id, b"secret", format).unwrap();
+    assert_eq!(w, UnlockedWallet::load_from(&store, b"secret").unwrap());
+    assert!(UnlockedWallet::load_from(&store, b"wrong").is_err());
+    drop(store);
+    std::fs::remove_dir_all(dir).unwrap();
+}
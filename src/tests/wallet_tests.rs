@@ -1,7 +1,12 @@
 use crypto_secretbox::{KeyInit, XSalsa20Poly1305};
 use rand::rngs::OsRng;
 
-use crate::{wallet::key_id_generate, LockedWallet, UnlockedWallet};
+use crate::{
+    error::Error,
+    key_formats::KeyFormat,
+    wallet::{key_id_generate, KdfParams, KeyRetention, KeyType, LockFormat, MAX_KDF_M_COST},
+    Attachment, Iml, LockedWallet, UnlockedWallet,
+};
 
 #[test]
 fn serialization_deserialization_test() {
@@ -12,13 +17,28 @@ fn serialization_deserialization_test() {
     assert_eq!(w, de_s);
 }
 
+#[test]
+fn interaction_rotation_serialization_test() {
+    let mut w = UnlockedWallet::new();
+    w.set_interaction_grace(std::time::Duration::from_secs(60));
+    let old = w.new_key(KeyType::X25519, None).unwrap();
+    let new = w.rotate_interaction_key(&old, KeyType::X25519, 3).unwrap();
+    assert_ne!(old, new);
+    assert!(w.rotate_interaction_key(&new, KeyType::Ed25519, 4).is_err());
+    let de_s: UnlockedWallet = serde_cbor::from_slice(&serde_cbor::to_vec(&w).unwrap()).unwrap();
+    assert_eq!(w, de_s);
+    assert_eq!(std::time::Duration::from_secs(60), de_s.interaction_grace());
+    // still within grace
+    assert!(w.purge_expired_keys().is_empty());
+}
+
 #[test]
 fn lock_and_unlock_test() {
     let mut w = UnlockedWallet::new();
     w.new_key_for(key_id_generate("ivan")).unwrap();
     let to_compare = w.clone();
     let test_pass = XSalsa20Poly1305::generate_key(&mut OsRng);
-    let locked_raw = w.lock(&test_pass).unwrap();
+    let locked_raw = w.lock(test_pass).unwrap();
     let unlocked: UnlockedWallet = LockedWallet::new(locked_raw)
         .unlock(test_pass.to_vec())
         .unwrap();
@@ -49,3 +69,378 @@ fn diffie_hellman_generation_works_test() {
     let b_dh = bob.diffie_hellman(&BOB_ID, a_pub).unwrap();
     assert_eq!(a_dh, b_dh);
 }
+
+#[test]
+fn x25519_diffie_hellman_test() {
+    let mut alice = UnlockedWallet::new();
+    let mut bob = UnlockedWallet::new();
+    let a_id = alice.new_key(KeyType::X25519, None).unwrap();
+    let b_id = bob.new_key(KeyType::X25519, None).unwrap();
+    let a_pub = alice.public_for(&a_id, KeyType::X25519).unwrap();
+    let b_pub = bob.public_for(&b_id, KeyType::X25519).unwrap();
+    assert_eq!(32, a_pub.len());
+    let a_dh = alice.diffie_hellman(&a_id, &b_pub).unwrap();
+    let b_dh = bob.diffie_hellman(&b_id, &a_pub).unwrap();
+    assert_eq!(a_dh, b_dh);
+    // low order point yields all-zero secret
+    assert!(alice
+        .diffie_hellman_as(&a_id, [0u8; 32], KeyType::X25519)
+        .is_err());
+}
+
+#[test]
+fn superseded_keys_retirement_test() {
+    let mut w = UnlockedWallet::with_retention(KeyRetention::RetireSuperseded);
+    let iml = Iml::new(&mut w).unwrap();
+    let iml = iml.evolve(&mut w, true, None).evolve(&mut w, true, None);
+    let sk_0 = key_id_generate("sk_0");
+    assert!(w.is_retired(&sk_0));
+    assert!(matches!(w.sign_with(b"msg", &sk_0), Err(Error::KeyRetired)));
+    assert!(w.public_for(&sk_0, KeyType::Ed25519_256).is_some());
+    // current controller is kept
+    assert!(!w.is_retired(&key_id_generate("sk_2")));
+    assert!(iml.verify());
+    // retirement survives serialization
+    let de_s: UnlockedWallet = serde_cbor::from_slice(&serde_cbor::to_vec(&w).unwrap()).unwrap();
+    assert_eq!(w, de_s);
+    assert_eq!(iml, Iml::re_evolve(&de_s, iml.get_id(), None));
+}
+
+#[test]
+fn retired_key_of_several_states_test() {
+    let mut w = UnlockedWallet::with_retention(KeyRetention::RetireSuperseded);
+    let iml = Iml::new(&mut w).unwrap().evolve(&mut w, true, None).evolve(
+        &mut w,
+        false,
+        Some(vec![Attachment::new("data", "text")]),
+    );
+    let attachments = iml.attachments();
+    let iml = iml.evolve(&mut w, true, None).evolve(&mut w, true, None);
+    // sk_1 signed both civilization 1 and 2 before retirement
+    let sk_1 = key_id_generate("sk_1");
+    assert!(w.is_retired(&sk_1));
+    assert!(w.retired_proof(&sk_1, 1).is_some());
+    assert!(w
+        .retired_proof(&sk_1, 2)
+        .unwrap()
+        .proof_of_attachments
+        .is_some());
+    assert!(iml.verify());
+    let restored = Iml::re_evolve(&w, iml.get_id(), attachments);
+    assert_eq!(4, restored.get_civilization());
+    assert!(restored.verify());
+    assert_eq!(iml, restored);
+}
+
+#[test]
+fn keep_all_retention_test() {
+    // secrets are kept unless retirement is opted into
+    let mut w = UnlockedWallet::new();
+    assert_eq!(KeyRetention::KeepAll, w.retention());
+    let _ = Iml::new(&mut w).unwrap().evolve(&mut w, true, None);
+    let sk_0 = key_id_generate("sk_0");
+    assert!(!w.is_retired(&sk_0));
+    assert!(w.sign_with(b"msg", &sk_0).is_ok());
+}
+
+#[test]
+fn legacy_wallet_deserialization_test() {
+    #[derive(serde::Serialize)]
+    struct LegacyEntry {
+        id: [u8; 4],
+        sk: [u8; 32],
+    }
+    let mut w = UnlockedWallet::new();
+    let id = key_id_generate("ivan");
+    w.new_key_for(id).unwrap();
+    let legacy = vec![LegacyEntry {
+        id,
+        sk: k256::ecdsa::SigningKey::random(&mut OsRng)
+            .to_bytes()
+            .into(),
+    }];
+    let de_s: UnlockedWallet =
+        serde_cbor::from_slice(&serde_cbor::to_vec(&legacy).unwrap()).unwrap();
+    assert!(de_s.public_for(&id, KeyType::Ed25519_256).is_some());
+    assert_eq!(de_s.retention(), KeyRetention::KeepAll);
+}
+
+#[test]
+fn unlock_zeroizes_secret_test() {
+    let mut w = UnlockedWallet::new();
+    w.new_key_for(key_id_generate("ivan")).unwrap();
+    let to_compare = w.clone();
+    let mut test_pass = XSalsa20Poly1305::generate_key(&mut OsRng).to_vec();
+    let locked_raw = w.lock(&test_pass).unwrap();
+    let unlocked = LockedWallet::new(locked_raw)
+        .unlock(test_pass.as_mut_slice())
+        .unwrap();
+    assert_eq!(unlocked, to_compare);
+    assert!(test_pass.iter().all(|b| *b == 0));
+}
+
+#[test]
+fn change_secret_test() {
+    let mut w = UnlockedWallet::new();
+    w.new_key_for(key_id_generate("ivan")).unwrap();
+    let to_compare = w.clone();
+    let old_pass = XSalsa20Poly1305::generate_key(&mut OsRng);
+    let new_pass = XSalsa20Poly1305::generate_key(&mut OsRng);
+    let locked = LockedWallet::new(w.lock(old_pass).unwrap());
+    assert!(locked
+        .change_secret(new_pass.to_vec(), new_pass.to_vec())
+        .is_err());
+    let changed = locked
+        .change_secret(old_pass.to_vec(), new_pass.to_vec())
+        .unwrap();
+    assert_eq!(changed.format(), LockFormat::Legacy);
+    assert!(LockedWallet::new(changed.content().to_vec())
+        .unlock(old_pass.to_vec())
+        .is_err());
+    assert_eq!(changed.unlock(new_pass.to_vec()).unwrap(), to_compare);
+    // failed change leaves original intact
+    assert_eq!(locked.unlock(old_pass.to_vec()).unwrap(), to_compare);
+}
+
+#[test]
+fn kdf_params_bounds_test() {
+    let kdf = KdfParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+    let locked = UnlockedWallet::new()
+        .lock_with(b"pass", LockFormat::V1(kdf))
+        .unwrap();
+    // crafted header must not trigger derivation
+    for (at, value) in [
+        (5, u32::MAX),
+        (5, 0),
+        (9, u32::MAX),
+        (9, 0),
+        (13, u32::MAX),
+        (13, 0),
+    ] {
+        let mut crafted = locked.clone();
+        crafted[at..at + 4].copy_from_slice(&u32::to_be_bytes(value));
+        assert!(matches!(
+            LockedWallet::new(crafted).unlock(b"pass".to_vec()),
+            Err(Error::KdfFailed(_))
+        ));
+    }
+    let huge = KdfParams {
+        m_cost: MAX_KDF_M_COST + 1,
+        ..kdf
+    };
+    assert!(matches!(
+        UnlockedWallet::new().lock_with(b"pass", LockFormat::V1(huge)),
+        Err(Error::KdfFailed(_))
+    ));
+}
+
+#[test]
+fn rekey_format_upgrade_test() {
+    let mut w = UnlockedWallet::new();
+    w.new_key_for(key_id_generate("ivan")).unwrap();
+    let to_compare = w.clone();
+    let old_pass = XSalsa20Poly1305::generate_key(&mut OsRng);
+    let kdf = KdfParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+    let upgraded = LockedWallet::new(w.lock(old_pass).unwrap())
+        .rekey(
+            old_pass.to_vec(),
+            b"correct horse battery staple".to_vec(),
+            LockFormat::V1(kdf),
+        )
+        .unwrap();
+    assert_eq!(upgraded.format(), LockFormat::V1(kdf));
+    let stronger = KdfParams { t_cost: 2, ..kdf };
+    let upgraded = upgraded
+        .rekey(
+            b"correct horse battery staple".to_vec(),
+            b"correct horse battery staple".to_vec(),
+            LockFormat::V1(stronger),
+        )
+        .unwrap();
+    assert_eq!(upgraded.format(), LockFormat::V1(stronger));
+    assert_eq!(
+        upgraded
+            .unlock(b"correct horse battery staple".to_vec())
+            .unwrap(),
+        to_compare
+    );
+}
+
+#[test]
+fn key_formats_export_import_test() {
+    let mut w = UnlockedWallet::new();
+    for key_type in [KeyType::Ed25519_256, KeyType::EcdhP256] {
+        let id = w.new_key(key_type, None).unwrap();
+        for format in [KeyFormat::Jwk, KeyFormat::Pkcs8Pem, KeyFormat::Sec1Pem] {
+            let exported = w.export_key(&id, key_type, format).unwrap();
+            let mut other = UnlockedWallet::new();
+            // same id is derived from public key
+            assert_eq!(
+                id,
+                other
+                    .import_key(key_type, format, &*exported, None)
+                    .unwrap()
+            );
+            assert_eq!(w.public_for(&id, key_type), other.public_for(&id, key_type));
+        }
+    }
+    let p256_id = w.new_key(KeyType::EcdhP256, None).unwrap();
+    let p256_jwk = w
+        .export_key(&p256_id, KeyType::EcdhP256, KeyFormat::Jwk)
+        .unwrap();
+    assert!(p256_jwk.contains(r#""crv":"P-256""#));
+    assert!(matches!(
+        w.import_key(KeyType::Ed25519_256, KeyFormat::Jwk, &*p256_jwk, None),
+        Err(Error::InvalidKeyEncoding)
+    ));
+    // stored key type must match requested one
+    assert!(matches!(
+        w.export_key(&p256_id, KeyType::Ed25519_256, KeyFormat::Jwk),
+        Err(Error::InvalidKeyEncoding)
+    ));
+    assert!(matches!(
+        w.export_key(&p256_id, KeyType::EcdsaP256, KeyFormat::Jwk),
+        Err(Error::InvalidKeyEncoding)
+    ));
+}
+
+#[test]
+fn key_formats_all_types_test() {
+    let mut w = UnlockedWallet::new();
+    for (key_type, formats) in [
+        (
+            KeyType::EcdsaP256,
+            &[KeyFormat::Jwk, KeyFormat::Pkcs8Pem, KeyFormat::Sec1Pem][..],
+        ),
+        (KeyType::Ed25519, &[KeyFormat::Jwk, KeyFormat::Pkcs8Pem][..]),
+        (KeyType::X25519, &[KeyFormat::Jwk, KeyFormat::Pkcs8Pem][..]),
+    ] {
+        let id = w.new_key(key_type, None).unwrap();
+        for format in formats {
+            let exported = w.export_key(&id, key_type, *format).unwrap();
+            let mut other = UnlockedWallet::new();
+            assert_eq!(
+                id,
+                other
+                    .import_key(key_type, *format, &*exported, None)
+                    .unwrap()
+            );
+            assert_eq!(w.public_for(&id, key_type), other.public_for(&id, key_type));
+        }
+    }
+    let ed25519 = w.new_key(KeyType::Ed25519, None).unwrap();
+    let jwk = w
+        .export_key(&ed25519, KeyType::Ed25519, KeyFormat::Jwk)
+        .unwrap();
+    assert!(jwk.contains(r#""kty":"OKP""#) && jwk.contains(r#""crv":"Ed25519""#));
+    // OKP keys have no SEC1 encoding
+    assert!(matches!(
+        w.export_key(&ed25519, KeyType::Ed25519, KeyFormat::Sec1Pem),
+        Err(Error::UnsupportedKeyType)
+    ));
+    assert!(matches!(
+        w.import_key(KeyType::X25519, KeyFormat::Sec1Pem, "", None),
+        Err(Error::UnsupportedKeyType)
+    ));
+    // curve of encoded key must match requested type
+    assert!(matches!(
+        w.import_key(KeyType::X25519, KeyFormat::Jwk, &*jwk, None),
+        Err(Error::InvalidKeyEncoding)
+    ));
+    let pem = w
+        .export_key(&ed25519, KeyType::Ed25519, KeyFormat::Pkcs8Pem)
+        .unwrap();
+    assert!(matches!(
+        w.import_key(KeyType::X25519, KeyFormat::Pkcs8Pem, &*pem, None),
+        Err(Error::InvalidKeyEncoding)
+    ));
+}
+
+#[test]
+fn sign_as_verify_test() {
+    let mut w = UnlockedWallet::new();
+    for key_type in [KeyType::Ed25519_256, KeyType::Ed25519, KeyType::EcdsaP256] {
+        let id = w.new_key(key_type, None).unwrap();
+        assert_eq!(Some(key_type), w.key_type(&id));
+        let public = w.public_for(&id, key_type).unwrap();
+        let signature = w.sign_as(b"msg", &id, key_type).unwrap();
+        assert!(key_type.verify(&public, b"msg", &signature));
+        assert!(!key_type.verify(&public, b"other msg", &signature));
+    }
+    let ed25519 = w.new_key(KeyType::Ed25519, None).unwrap();
+    assert_eq!(32, w.public_for(&ed25519, KeyType::Ed25519).unwrap().len());
+    let dh = w.new_key(KeyType::EcdhP256, None).unwrap();
+    assert!(w.sign_as(b"msg", &dh, KeyType::EcdhP256).is_err());
+    // secp256k1 only API does not sign with keys of other types
+    assert!(matches!(
+        w.sign_with(b"msg", &ed25519),
+        Err(Error::UnsupportedKeyType)
+    ));
+    let k256 = w.new_key(KeyType::Ed25519_256, None).unwrap();
+    let signature = w.sign_with(b"msg", &k256).unwrap();
+    assert!(w.verify_with(b"msg", &k256, &signature));
+    assert!(!w.verify_with(b"msg", &ed25519, &signature));
+}
+
+#[test]
+fn verification_key_formats_test() {
+    let mut w = UnlockedWallet::new();
+    let iml = Iml::new(&mut w).unwrap();
+    let did = iml.did();
+    let current = iml.current_sk_key();
+    assert_eq!(format!("{did}#sk-0"), current.id);
+    let jwk = current.to_jwk().unwrap();
+    assert_eq!("EC", jwk["kty"]);
+    assert_eq!("secp256k1", jwk["crv"]);
+    assert_eq!(current.id, jwk["kid"]);
+    assert!(jwk["x"].is_string() && jwk["y"].is_string() && jwk.get("d").is_none());
+    assert!(current.to_multikey().unwrap().starts_with("zQ3s"));
+    let interaction = iml.interaction_verification_key();
+    let hash = blake3::hash(&iml.get_interacion_key());
+    assert_eq!(
+        format!("{did}#ik-{}", hex::encode(&hash.as_bytes()[..16])),
+        interaction.id
+    );
+    assert_eq!("P-256", interaction.to_jwk().unwrap()["crv"]);
+    assert!(interaction.to_multikey().unwrap().starts_with("zDn"));
+
+    // next key keeps it's id once it becomes current
+    let next = iml.next_sk_key().unwrap();
+    let iml = iml.evolve(&mut w, true, None);
+    assert_eq!(next, iml.current_sk_key());
+    let iml = iml
+        .add_service(
+            &mut w,
+            crate::service::Service::new("#inbox", "DIDCommMessaging", "https://example.com"),
+        )
+        .unwrap();
+    assert_eq!(next, iml.current_sk_key());
+    assert_eq!(interaction, iml.interaction_verification_key());
+    // pairwise view of the same state has it's own key id
+    let pairwise = w.pairwise_key("did:key:peer", KeyType::EcdhP256).unwrap();
+    let view = iml.pairwise_view(&w, &pairwise).unwrap();
+    assert_ne!(interaction.id, view.interaction_verification_key().id);
+    let iml = iml.rotate_interaction_key(&mut w).unwrap();
+    assert_ne!(interaction.id, iml.interaction_verification_key().id);
+    assert!(iml.discontinue(&mut w).unwrap().next_sk_key().is_none());
+
+    let mut w = UnlockedWallet::new();
+    let iml = Iml::new_with(&mut w, KeyType::Ed25519, KeyType::X25519).unwrap();
+    let jwk = iml.interaction_verification_key().to_jwk().unwrap();
+    assert_eq!("OKP", jwk["kty"]);
+    assert_eq!("X25519", jwk["crv"]);
+    assert_eq!(43, jwk["x"].as_str().unwrap().len());
+    assert!(iml
+        .current_sk_key()
+        .to_multikey()
+        .unwrap()
+        .starts_with("z6Mk"));
+}
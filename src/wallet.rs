@@ -1,4 +1,6 @@
 use crate::error::Error;
+use argon2::{Algorithm, Argon2, Params, Version};
+use arrayref::array_ref;
 use blake3::hash;
 use crypto_secretbox::{
     aead::{Aead, AeadCore, KeyInit, OsRng},
@@ -10,14 +12,109 @@ use k256::ecdsa::{
     SigningKey, VerifyingKey,
 };
 use rand::RngCore;
-use serde::{ser::SerializeSeq, Deserialize, Serialize};
+use serde::{
+    de::{IgnoredAny, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeMap, SerializeSeq},
+    Deserialize, Serialize,
+};
 use static_dh_ecdh::ecdh::ecdh::{FromBytes, KeyExchange, PkP256, SkP256, ToBytes, ECDHNISTP256};
-use std::collections::HashMap;
-use zeroize::Zeroize;
+use std::{
+    collections::HashMap,
+    io::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
 #[cfg_attr(test, derive(Debug, PartialEq, Clone))]
 pub struct UnlockedWallet {
-    keys: HashMap<KeyId, [u8; 32]>,
+    keys: HashMap<KeyId, WalletKey>,
+    /// Public halves of signing keys which secrets were destroyed
+    ///  after being superseded by evolution.
+    ///
+    retired: HashMap<KeyId, RetiredKey>,
+    /// Which secrets must be kept once superseded.
+    ///
+    retention: KeyRetention,
+    /// For how long superseded interaction keys are still usable for decryption.
+    ///
+    interaction_grace: Duration,
+}
+
+/// Default [UnlockedWallet::interaction_grace] - one week
+pub const DEFAULT_INTERACTION_GRACE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Policy defining which secrets [UnlockedWallet] keeps after
+///  they were superseded by `Iml` evolution.
+/// Destroying secrets is irreversible, so retirement is opt-in:
+///  new and deserialized wallets without stored policy keep everything.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyRetention {
+    /// Every secret is kept forever.
+    /// Leaking the wallet exposes every historical signing key.
+    #[default]
+    KeepAll,
+    /// Signing secrets are destroyed as soon as they are superseded,
+    ///  only their public keys and proofs of states they signed are kept.
+    RetireSuperseded,
+}
+
+/// Signatures of single `Iml` state, kept when it's signing secret is destroyed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StateProof {
+    pub civilization: u64,
+    pub proof: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof_of_attachments: Option<Vec<u8>>,
+}
+
+/// Remains of a destroyed signing secret.
+/// Sufficient to re-evolve and verify, but not to sign.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct RetiredKey {
+    key_type: KeyType,
+    public: Vec<u8>,
+    /// Last signature produced with this key before retirement,
+    ///  the only one kept by earlier versions
+    proof: Option<Vec<u8>>,
+    /// Signatures of every state signed with this key
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    proofs: Vec<StateProof>,
+    /// Civilization this interaction key was introduced at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    civilization: Option<u64>,
+}
+
+/// Secret key with it's type.
+/// Type is unknown for keys stored before types were tracked.
+#[cfg_attr(test, derive(Debug, PartialEq))]
+#[derive(Clone)]
+struct WalletKey {
+    sk: Zeroizing<[u8; 32]>,
+    key_type: Option<KeyType>,
+    /// Civilization this interaction key was introduced at
+    civilization: Option<u64>,
+    /// Unix time (seconds) this interaction key was superseded by rotation at
+    superseded_at: Option<u64>,
+    /// Identifier of the peer this pairwise interaction key is dedicated to
+    peer: Option<String>,
+}
+
+impl WalletKey {
+    fn new(sk: Zeroizing<[u8; 32]>, key_type: KeyType) -> Self {
+        Self {
+            sk,
+            key_type: Some(key_type),
+            civilization: None,
+            superseded_at: None,
+            peer: None,
+        }
+    }
+}
+
+impl Zeroize for WalletKey {
+    fn zeroize(&mut self) {
+        self.sk.zeroize();
+    }
 }
 
 impl Zeroize for UnlockedWallet {
@@ -26,6 +123,14 @@ impl Zeroize for UnlockedWallet {
     }
 }
 
+impl Drop for UnlockedWallet {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for UnlockedWallet {}
+
 impl Default for UnlockedWallet {
     fn default() -> Self {
         Self::new()
@@ -34,55 +139,249 @@ impl Default for UnlockedWallet {
 
 impl UnlockedWallet {
     pub fn new() -> Self {
+        Self::with_retention(KeyRetention::default())
+    }
+
+    /// Constructs empty wallet with given [KeyRetention] policy
+    pub fn with_retention(retention: KeyRetention) -> Self {
         Self {
             keys: HashMap::new(),
+            retired: HashMap::new(),
+            retention,
+            interaction_grace: DEFAULT_INTERACTION_GRACE,
         }
     }
 
-    pub fn lock(mut self, pass: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
-        let locked = serde_cbor::to_vec(&self)?;
+    pub fn retention(&self) -> KeyRetention {
+        self.retention
+    }
+
+    pub fn set_retention(&mut self, retention: KeyRetention) {
+        self.retention = retention;
+    }
+
+    /// For how long superseded interaction keys are still usable for decryption
+    pub fn interaction_grace(&self) -> Duration {
+        self.interaction_grace
+    }
+
+    pub fn set_interaction_grace(&mut self, grace: Duration) {
+        // stored with seconds precision
+        self.interaction_grace = Duration::from_secs(grace.as_secs());
+    }
+
+    /// Empty wallet with same settings as self
+    pub(crate) fn settings(&self) -> Self {
+        let mut wallet = Self::with_retention(self.retention);
+        wallet.interaction_grace = self.interaction_grace;
+        wallet
+    }
+
+    /// Encrypts wallet in [LockFormat::Legacy] format.
+    /// `pass` is used as encryption key as is and must be 32 bytes long.
+    pub fn lock(self, pass: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+        self.lock_with(pass, LockFormat::Legacy)
+    }
+
+    /// Encrypts wallet in given [LockFormat]
+    /// # Parameters
+    /// - `pass` - secret phrase to encrypt content with
+    /// - `format` - locked content layout and key derivation
+    /// # Returns
+    /// - encrypted content for [LockedWallet]
+    pub fn lock_with(self, pass: impl AsRef<[u8]>, format: LockFormat) -> Result<Vec<u8>, Error> {
+        self.lock_cached(pass, format, &mut KdfCache::default())
+    }
+
+    /// [UnlockedWallet::lock_with] reusing salt and key derived for the same
+    ///  [KdfParams] within `cache`, so many records are sealed with single derivation
+    pub(crate) fn lock_cached(
+        mut self,
+        pass: impl AsRef<[u8]>,
+        format: LockFormat,
+        cache: &mut KdfCache,
+    ) -> Result<Vec<u8>, Error> {
+        let plaintext = to_cbor_zeroizing(&self)?;
         self.zeroize();
-        let (cipher, nonce) = build_cypher_and_random_nonce(pass)?;
-        let mut locked = cipher.encrypt(&nonce, locked.as_ref())?.to_vec();
-        locked.extend_from_slice(nonce.as_slice());
-        Ok(locked)
+        seal(&plaintext, pass.as_ref(), format, cache)
     }
 
+    /// Generates new key of given type.
+    /// Key under `id` is overwritten if present.
+    /// Id is generated from public key if `id` is `None`.
     pub fn new_key(&mut self, key_type: KeyType, id: Option<KeyId>) -> Result<KeyId, Error> {
-        match key_type {
-            KeyType::Ed25519_256 => {
-                let new_key = SigningKey::random(&mut OsRng {});
-                let id = if let Some(id) = id {
-                    id
-                } else {
-                    key_id_generate(new_key.verifying_key().to_sec1_bytes())
-                };
-                self.keys.insert(id, new_key.to_bytes().into());
-                Ok(id)
+        let sk = key_type.generate();
+        let id = match id {
+            Some(id) => id,
+            None => key_id_generate(key_type.public_from(&sk).ok_or(Error::UnsupportedKeyType)?),
+        };
+        self.keys.insert(id, WalletKey::new(sk, key_type));
+        Ok(id)
+    }
+
+    /// Generates new interaction key replacing `old_id` one since given civilization.
+    /// Old key is kept usable for decryption for [UnlockedWallet::interaction_grace].
+    /// Pairwise keys are superseded as well, new ones are generated on next use.
+    /// # Parameters
+    /// - `old_id` - id of superseded interaction key
+    /// - `key_type` - key agreement type of the new key
+    /// - `civilization` - civilization new key is introduced at
+    /// # Returns
+    /// - id of the new key
+    pub fn rotate_interaction_key(
+        &mut self,
+        old_id: &KeyId,
+        key_type: KeyType,
+        civilization: u64,
+    ) -> Result<KeyId, Error> {
+        if !key_type.is_agreement() {
+            return Err(Error::UnsupportedKeyType);
+        }
+        let now = unix_now();
+        let old = self.keys.get_mut(old_id).ok_or(Error::KeyNotFound)?;
+        old.superseded_at.get_or_insert(now);
+        self.keys
+            .values_mut()
+            .filter(|key| key.peer.is_some())
+            .for_each(|key| {
+                key.superseded_at.get_or_insert(now);
+            });
+        self.new_interaction_key(key_type, civilization)
+    }
+
+    /// Generates new interaction key introduced at given civilization,
+    ///  so it can be found on re-evolution.
+    /// Key previously introduced at same civilization is not found anymore.
+    /// Id is generated from public key.
+    pub fn new_interaction_key(
+        &mut self,
+        key_type: KeyType,
+        civilization: u64,
+    ) -> Result<KeyId, Error> {
+        if !key_type.is_agreement() {
+            return Err(Error::UnsupportedKeyType);
+        }
+        let id = self.new_key(key_type, None)?;
+        // previous identifier's key of the same civilization is overwritten
+        self.keys
+            .values_mut()
+            .filter(|key| key.civilization == Some(civilization))
+            .for_each(|key| key.civilization = None);
+        if let Some(key) = self.keys.get_mut(&id) {
+            key.civilization = Some(civilization);
+        }
+        Ok(id)
+    }
+
+    /// Interaction key dedicated to relationship with `peer`, generated on first use
+    /// # Parameters
+    /// - `peer` - identifier of the peer
+    /// - `key_type` - key agreement type of the key
+    /// # Returns
+    /// - id of the pairwise key
+    pub fn pairwise_key(&mut self, peer: &str, key_type: KeyType) -> Result<KeyId, Error> {
+        if !key_type.is_agreement() {
+            return Err(Error::UnsupportedKeyType);
+        }
+        if let Some(id) = self.pairwise_key_for(peer, key_type) {
+            return Ok(id);
+        }
+        let id = self.new_key(key_type, None)?;
+        if let Some(key) = self.keys.get_mut(&id) {
+            key.peer = Some(peer.to_string());
+        }
+        Ok(id)
+    }
+
+    /// Id of interaction key dedicated to relationship with `peer`, if any and not superseded
+    pub fn pairwise_key_for(&self, peer: &str, key_type: KeyType) -> Option<KeyId> {
+        self.keys
+            .iter()
+            .find(|(_, key)| {
+                key.peer.as_deref() == Some(peer)
+                    && key.key_type == Some(key_type)
+                    && key.superseded_at.is_none()
+            })
+            .map(|(id, _)| *id)
+    }
+
+    /// Ids of all pairwise interaction keys, superseded ones within grace period included
+    pub(crate) fn pairwise_keys(&self) -> Vec<KeyId> {
+        self.keys
+            .iter()
+            .filter(|(_, key)| key.peer.is_some())
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Type and public key of interaction key introduced at given civilization
+    pub(crate) fn interaction_key_at(&self, civilization: u64) -> Option<(KeyType, Vec<u8>)> {
+        let at = Some(civilization);
+        self.keys
+            .iter()
+            .find(|(_, key)| key.civilization == at)
+            .and_then(|(id, key)| {
+                let key_type = key.key_type?;
+                Some((key_type, self.public_for(id, key_type)?))
+            })
+            .or_else(|| {
+                self.retired
+                    .values()
+                    .find(|r| r.civilization == at)
+                    .map(|r| (r.key_type, r.public.clone()))
+            })
+    }
+
+    /// Destroys secrets of superseded interaction keys past their grace period.
+    /// Public keys are kept for re-evolution.
+    /// # Returns
+    /// - ids of retired keys, to be saved into [KeyStore](crate::store::KeyStore)
+    pub fn purge_expired_keys(&mut self) -> Vec<KeyId> {
+        let expired: Vec<KeyId> = self
+            .keys
+            .iter()
+            .filter(|(_, key)| self.is_expired(key))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired.iter() {
+            if let Some(key) = self.keys.remove(id) {
+                let key_type = key.key_type.unwrap_or(KeyType::EcdhP256);
+                self.retired.insert(
+                    *id,
+                    RetiredKey {
+                        key_type,
+                        public: key_type.public_from(&key.sk).unwrap_or_default(),
+                        proof: None,
+                        proofs: vec![],
+                        civilization: key.civilization,
+                    },
+                );
             }
-            KeyType::EcdhP256 => {
-                let mut seed = [0u8; 32];
-                OsRng.fill_bytes(&mut seed);
-                let new_sk = ECDHNISTP256::generate_private_key(seed);
-                let new_pk = ECDHNISTP256::generate_public_key(&new_sk);
-                let key_id = if let Some(id) = id {
-                    id
-                } else {
-                    key_id_generate(new_pk.to_bytes())
-                };
-                self.keys.insert(key_id, new_sk.to_bytes().into());
-                Ok(key_id)
-            } //_ => Err(Error::UnsupportedKeyType),
         }
+        expired
     }
 
+    fn is_expired(&self, key: &WalletKey) -> bool {
+        key.superseded_at
+            .is_some_and(|at| at.saturating_add(self.interaction_grace.as_secs()) <= unix_now())
+    }
+
+    /// Generates new [KeyType::Ed25519_256] key under `id`, failing if `id` is taken
     pub fn new_key_for(&mut self, id: KeyId) -> Result<(), Error> {
-        if self.keys.get(&id).is_some() {
-            Err(Error::KeyExistsForId)
-        } else {
-            self.keys
-                .insert(id, SigningKey::random(&mut OsRng {}).to_bytes().into());
-            Ok(())
+        self.new_typed_key_for(KeyType::Ed25519_256, id)
+    }
+
+    /// Generates new key of given type under `id`, failing if `id` is taken
+    pub fn new_typed_key_for(&mut self, key_type: KeyType, id: KeyId) -> Result<(), Error> {
+        self.insert_secret(id, key_type.generate(), key_type)
+            .map(drop)
+    }
+
+    /// Type of the key, if known
+    pub fn key_type(&self, id: &KeyId) -> Option<KeyType> {
+        match self.keys.get(id) {
+            Some(key) => key.key_type,
+            None => self.retired.get(id).map(|r| r.key_type),
         }
     }
 
@@ -96,34 +395,145 @@ impl UnlockedWallet {
         }
     }
 
-    pub fn public_for(&self, id: &KeyId, key_type: KeyType) -> Option<Vec<u8>> {
-        let sk_bytes = self.keys.get(id)?;
-        match key_type {
-            KeyType::Ed25519_256 => {
-                if let Ok(sk) = SigningKey::from_slice(sk_bytes) {
-                    let vk = sk.verifying_key().to_sec1_bytes();
-                    Some(vk.to_vec())
-                } else {
-                    None
-                }
-            }
-            KeyType::EcdhP256 => {
-                if let Ok(sk) = SkP256::from_bytes(sk_bytes) {
-                    let pk = ECDHNISTP256::generate_public_key(&sk).to_bytes();
-                    Some(pk.to_vec())
-                } else {
-                    None
-                }
+    /// Destroys secret of superseded key if [KeyRetention] policy requires so.
+    /// Public key and `proofs` are kept for re-evolution and verification.
+    ///
+    /// # Parameters
+    /// - `id` - id of the superseded key
+    /// - `key_type` - type of the key
+    /// - `proofs` - signatures of every state signed with this key
+    /// # Returns
+    /// - `true` if secret was destroyed
+    pub fn retire_key(
+        &mut self,
+        id: &KeyId,
+        key_type: KeyType,
+        proofs: Vec<StateProof>,
+    ) -> Result<bool, Error> {
+        if self.retention == KeyRetention::KeepAll {
+            return Ok(false);
+        }
+        let public = self.public_for(id, key_type).ok_or(Error::KeyNotFound)?;
+        // dropped `Zeroizing` scrubs the secret
+        self.keys.remove(id);
+        self.retired.insert(
+            *id,
+            RetiredKey {
+                key_type,
+                public,
+                proof: proofs
+                    .iter()
+                    .max_by_key(|state| state.civilization)
+                    .map(|state| state.proof.clone()),
+                proofs,
+                civilization: None,
+            },
+        );
+        Ok(true)
+    }
+
+    /// Checks if secret of the key was destroyed by retirement
+    pub fn is_retired(&self, id: &KeyId) -> bool {
+        self.retired.contains_key(id)
+    }
+
+    /// Ids of all keys, retired included
+    pub fn key_ids(&self) -> Vec<KeyId> {
+        self.keys
+            .keys()
+            .chain(self.retired.keys())
+            .copied()
+            .collect()
+    }
+
+    /// Secret bytes of the key
+    pub(crate) fn secret(&self, id: &KeyId) -> Result<&Zeroizing<[u8; 32]>, Error> {
+        match self.keys.get(id) {
+            Some(key) => Ok(&key.sk),
+            None if self.is_retired(id) => Err(Error::KeyRetired),
+            None => Err(Error::KeyNotFound),
+        }
+    }
+
+    /// Inserts secret bytes, failing if `id` is taken
+    pub(crate) fn insert_secret(
+        &mut self,
+        id: KeyId,
+        sk: Zeroizing<[u8; 32]>,
+        key_type: KeyType,
+    ) -> Result<KeyId, Error> {
+        if self.keys.contains_key(&id) || self.retired.contains_key(&id) {
+            return Err(Error::KeyExistsForId);
+        }
+        self.keys.insert(id, WalletKey::new(sk, key_type));
+        Ok(id)
+    }
+
+    /// Wallet holding only given key (or it's retired remains) and settings.
+    /// `None` if there is no such key.
+    pub(crate) fn fragment(&self, id: &KeyId) -> Option<Self> {
+        let mut fragment = self.settings();
+        if let Some(key) = self.keys.get(id) {
+            fragment.keys.insert(*id, key.clone());
+        } else {
+            fragment.retired.insert(*id, self.retired.get(id)?.clone());
+        }
+        Some(fragment)
+    }
+
+    /// Moves all keys of `other` into self.
+    /// Retired remains replace secrets under same id.
+    pub(crate) fn merge(&mut self, mut other: Self) {
+        other.keys.drain().for_each(|(id, key)| {
+            if !self.retired.contains_key(&id) {
+                self.keys.insert(id, key);
             }
+        });
+        other.retired.drain().for_each(|(id, retired)| {
+            self.keys.remove(&id);
+            self.retired.insert(id, retired);
+        });
+    }
+
+    /// Signatures of state at `civilization` generated by retired key before it's secret was destroyed.
+    /// Keys retired by earlier versions have only last signature kept, returned for any civilization.
+    pub fn retired_proof(&self, id: &KeyId, civilization: u64) -> Option<StateProof> {
+        let retired = self.retired.get(id)?;
+        if retired.proofs.is_empty() {
+            return retired.proof.clone().map(|proof| StateProof {
+                civilization,
+                proof,
+                proof_of_attachments: None,
+            });
         }
+        retired
+            .proofs
+            .iter()
+            .find(|state| state.civilization == civilization)
+            .cloned()
+    }
+
+    pub fn public_for(&self, id: &KeyId, key_type: KeyType) -> Option<Vec<u8>> {
+        let Some(key) = self.keys.get(id) else {
+            return self
+                .retired
+                .get(id)
+                .filter(|r| r.key_type == key_type)
+                .map(|r| r.public.clone());
+        };
+        key_type.public_from(&key.sk)
     }
 
+    /// Verifies secp256k1 ECDSA `signature`, `false` for keys of other types
     pub fn verify_with(
         &self,
         message: impl AsRef<[u8]>,
         id: &KeyId,
         signature: &Signature,
     ) -> bool {
+        if !self.is_secp256k1(id) {
+            return false;
+        }
         if let Some(vk) = self.public_for(id, KeyType::Ed25519_256) {
             VerifyingKey::from_sec1_bytes(&vk)
                 .is_ok_and(|vk| vk.verify(message.as_ref(), signature).is_ok())
@@ -132,31 +542,69 @@ impl UnlockedWallet {
         }
     }
 
+    /// Signs `message` with secp256k1 ECDSA key.
+    /// Use [UnlockedWallet::sign_as] for keys of other types.
     pub fn sign_with(&self, message: impl AsRef<[u8]>, id: &KeyId) -> Result<Signature, Error> {
-        if let Some(sk) = self.keys.get(id) {
-            Ok(SigningKey::from_bytes(sk.into())
+        if !self.is_secp256k1(id) {
+            return Err(Error::UnsupportedKeyType);
+        }
+        if let Some(key) = self.keys.get(id) {
+            Ok(SigningKey::from_bytes(key.sk.as_ref().into())
                 .map_err(|_| Error::UnsupportedKeyType)?
                 .sign(message.as_ref()))
+        } else if self.is_retired(id) {
+            Err(Error::KeyRetired)
         } else {
             Err(Error::EcdsaFailed)
         }
     }
 
+    /// Untyped keys, stored before types were tracked, are secp256k1 ECDSA ones
+    fn is_secp256k1(&self, id: &KeyId) -> bool {
+        self.key_type(id)
+            .is_none_or(|key_type| key_type == KeyType::Ed25519_256)
+    }
+
+    /// Signs `message` with key of any signing [KeyType]
+    /// # Returns
+    /// - raw signature bytes, verifiable with [KeyType::verify]
+    pub fn sign_as(
+        &self,
+        message: impl AsRef<[u8]>,
+        id: &KeyId,
+        key_type: KeyType,
+    ) -> Result<Vec<u8>, Error> {
+        key_type.sign(self.secret(id)?, message.as_ref())
+    }
+
+    /// Diffie-Hellman shared secret with key of it's own type.
+    /// Keys of unknown or non key agreement type are treated as [KeyType::EcdhP256].
     pub fn diffie_hellman(
         &self,
         key_id: &KeyId,
         their_id: impl AsRef<[u8]>,
-    ) -> Result<Vec<u8>, Error> {
-        if let Some(sk) = self.keys.get(key_id) {
-            let our_s = SkP256::from_bytes(sk.as_ref())?;
-            let their_pk = PkP256::from_bytes(their_id.as_ref())?;
-            Ok(ECDHNISTP256::generate_shared_secret(&our_s, &their_pk)?
-                .to_bytes()
-                .into_iter()
-                .collect())
-        } else {
-            Err(Error::KeyNotFound)
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let key_type = match self.key_type(key_id) {
+            Some(key_type) if key_type.is_agreement() => key_type,
+            _ => KeyType::EcdhP256,
+        };
+        self.diffie_hellman_as(key_id, their_id, key_type)
+    }
+
+    /// Diffie-Hellman shared secret with key of given key agreement type
+    pub fn diffie_hellman_as(
+        &self,
+        key_id: &KeyId,
+        their_id: impl AsRef<[u8]>,
+        key_type: KeyType,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let Some(key) = self.keys.get(key_id) else {
+            return Err(Error::KeyNotFound);
+        };
+        if self.is_expired(key) {
+            return Err(Error::KeyExpired);
         }
+        key_type.agree(&key.sk, their_id)
     }
 }
 
@@ -178,27 +626,496 @@ impl LockedWallet {
         Self { content }
     }
 
+    /// Encrypted content of this wallet
+    pub fn content(&self) -> &[u8] {
+        &self.content
+    }
+
+    /// Detects [LockFormat] of the content
+    pub fn format(&self) -> LockFormat {
+        match LockHeader::parse(&self.content) {
+            Some((header, _)) => LockFormat::V1(header.kdf),
+            None => LockFormat::Legacy,
+        }
+    }
+
+    /// Re-encrypts content of this wallet with new secret, keeping it's [LockFormat].
+    /// Self stays untouched if `old` secret does not match.
+    /// # Parameters
+    /// - `old` - current secret phrase
+    /// - `new` - new secret phrase
+    /// # Returns
+    /// - [LockedWallet] encrypted with `new` secret
+    pub fn change_secret<O, N>(&self, old: O, new: N) -> Result<LockedWallet, Error>
+    where
+        O: AsRef<[u8]> + AsMut<[u8]>,
+        N: AsRef<[u8]> + AsMut<[u8]>,
+    {
+        self.rekey(old, new, self.format())
+    }
+
+    /// Re-encrypts content of this wallet with new secret into given [LockFormat].
+    /// Allows upgrading locked format version and it's KDF parameters in one go.
+    /// Self stays untouched on any failure. Both secrets are zeroized after use.
+    /// # Parameters
+    /// - `old` - current secret phrase
+    /// - `new` - new secret phrase
+    /// - `format` - target locked content layout and key derivation
+    /// # Returns
+    /// - [LockedWallet] encrypted with `new` secret
+    pub fn rekey<O, N>(
+        &self,
+        mut old: O,
+        mut new: N,
+        format: LockFormat,
+    ) -> Result<LockedWallet, Error>
+    where
+        O: AsRef<[u8]> + AsMut<[u8]>,
+        N: AsRef<[u8]> + AsMut<[u8]>,
+    {
+        let plaintext = open(&self.content, old.as_ref(), &mut KdfCache::default());
+        old.as_mut().zeroize();
+        let content = plaintext
+            .and_then(|plaintext| seal(&plaintext, new.as_ref(), format, &mut KdfCache::default()));
+        new.as_mut().zeroize();
+        Ok(LockedWallet::new(content?))
+    }
+
     /// Decrypts content of this wallet into [UnlockedWallet]
+    /// `secret` is zeroized after use.
     /// # Parameters
     /// - `secret` - secret phrase to decrypt content
     /// # Returns
     /// - [UnlockedWallet] instance
-    pub fn unlock<S>(self, secret: S) -> Result<UnlockedWallet, Error>
+    pub fn unlock<S>(self, mut secret: S) -> Result<UnlockedWallet, Error>
     where
         S: AsRef<[u8]> + AsMut<[u8]>,
     {
-        let (cipher, _) = build_cypher_and_random_nonce(secret)?;
-        let len = self.content.len();
-        let plaintext =
-            cipher.decrypt(self.content[len - 24..].into(), &self.content[..len - 24])?;
+        let plaintext = open(&self.content, secret.as_ref(), &mut KdfCache::default());
+        secret.as_mut().zeroize();
+        Ok(serde_cbor::from_slice(&plaintext?)?)
+    }
+
+    /// [LockedWallet::unlock] reusing keys already derived within `cache`
+    pub(crate) fn unlock_cached(
+        self,
+        secret: &[u8],
+        cache: &mut KdfCache,
+    ) -> Result<UnlockedWallet, Error> {
+        let plaintext = open(&self.content, secret, cache)?;
         Ok(serde_cbor::from_slice(&plaintext)?)
     }
 }
 
+/// Layout of [LockedWallet] content
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockFormat {
+    /// `ciphertext || nonce`, secret is used as XSalsa20Poly1305 key as is
+    Legacy,
+    /// Versioned header followed by `nonce || ciphertext`,
+    ///  key is derived from secret with Argon2id
+    V1(KdfParams),
+}
+
+impl Default for LockFormat {
+    fn default() -> Self {
+        Self::V1(KdfParams::default())
+    }
+}
+
+/// Argon2id parameters of [LockFormat::V1]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory size in KiB
+    pub m_cost: u32,
+    /// Number of iterations
+    pub t_cost: u32,
+    /// Degree of parallelism
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// Upper bound of [KdfParams::m_cost] - 1 GiB
+pub const MAX_KDF_M_COST: u32 = 1024 * 1024;
+/// Upper bound of [KdfParams::t_cost]
+pub const MAX_KDF_T_COST: u32 = 64;
+/// Upper bound of [KdfParams::p_cost]
+pub const MAX_KDF_P_COST: u32 = 16;
+
+impl KdfParams {
+    /// Rejects parameters outside of sane bounds, so crafted locked content
+    ///  can not force huge allocations or endless derivation before authentication
+    fn check(&self) -> Result<(), Error> {
+        let in_bounds = (1..=MAX_KDF_P_COST).contains(&self.p_cost)
+            && (1..=MAX_KDF_T_COST).contains(&self.t_cost)
+            && (Params::MIN_M_COST.max(8 * self.p_cost)..=MAX_KDF_M_COST).contains(&self.m_cost);
+        if !in_bounds {
+            return Err(Error::KdfFailed(format!(
+                "KDF parameters out of bounds: {self:?}"
+            )));
+        }
+        Ok(())
+    }
+
+    fn derive(&self, secret: &[u8], salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, Error> {
+        self.check()?;
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params).hash_password_into(
+            secret,
+            salt,
+            key.as_mut(),
+        )?;
+        Ok(key)
+    }
+}
+
+/// Keys derived by [KdfParams] per salt, for a single secret.
+/// Lets many records locked with the same secret share one Argon2id derivation.
+#[derive(Default)]
+pub(crate) struct KdfCache {
+    keys: Vec<(KdfParams, [u8; SALT_LEN], Zeroizing<[u8; 32]>)>,
+}
+
+impl KdfCache {
+    /// Key of given parameters and salt, derived on first use
+    fn key(
+        &mut self,
+        kdf: KdfParams,
+        salt: [u8; SALT_LEN],
+        secret: &[u8],
+    ) -> Result<&Zeroizing<[u8; 32]>, Error> {
+        let at = match self
+            .keys
+            .iter()
+            .position(|(k, s, _)| *k == kdf && *s == salt)
+        {
+            Some(at) => at,
+            None => {
+                self.keys.push((kdf, salt, kdf.derive(secret, &salt)?));
+                self.keys.len() - 1
+            }
+        };
+        Ok(&self.keys[at].2)
+    }
+
+    /// Salt already used with given parameters, if any
+    fn salt_for(&self, kdf: KdfParams) -> Option<[u8; SALT_LEN]> {
+        self.keys
+            .iter()
+            .find(|(k, _, _)| *k == kdf)
+            .map(|(_, s, _)| *s)
+    }
+}
+
+/// [LockFormat::V1] content header
+struct LockHeader {
+    kdf: KdfParams,
+    salt: [u8; SALT_LEN],
+}
+
+const LOCK_MAGIC: &[u8; 4] = b"IMLW";
+const LOCK_VERSION_1: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = LOCK_MAGIC.len() + 1 + 3 * 4 + SALT_LEN;
+
+impl LockHeader {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(LOCK_MAGIC);
+        bytes.push(LOCK_VERSION_1);
+        bytes.extend_from_slice(&self.kdf.m_cost.to_be_bytes());
+        bytes.extend_from_slice(&self.kdf.t_cost.to_be_bytes());
+        bytes.extend_from_slice(&self.kdf.p_cost.to_be_bytes());
+        bytes.extend_from_slice(&self.salt);
+        bytes
+    }
+
+    /// Splits content into header and rest, `None` for legacy content
+    fn parse(content: &[u8]) -> Option<(Self, &[u8])> {
+        if content.len() < HEADER_LEN + NONCE_LEN
+            || &content[..4] != LOCK_MAGIC
+            || content[4] != LOCK_VERSION_1
+        {
+            return None;
+        }
+        let u32_at = |at: usize| u32::from_be_bytes(*array_ref!(content, at, 4));
+        Some((
+            Self {
+                kdf: KdfParams {
+                    m_cost: u32_at(5),
+                    t_cost: u32_at(9),
+                    p_cost: u32_at(13),
+                },
+                salt: *array_ref!(content, 17, SALT_LEN),
+            },
+            &content[HEADER_LEN..],
+        ))
+    }
+}
+
+/// Counts bytes written, so serialized secrets can be written into buffer of exact size
+#[derive(Default)]
+struct ByteCounter(usize);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// CBOR of `value` in pre-sized buffer, so no reallocation leaves unscrubbed copies behind
+fn to_cbor_zeroizing(value: &impl Serialize) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let mut counter = ByteCounter::default();
+    serde_cbor::to_writer(&mut counter, value)?;
+    let mut plaintext = Zeroizing::new(Vec::with_capacity(counter.0));
+    let capacity = plaintext.capacity();
+    serde_cbor::to_writer(&mut *plaintext, value)?;
+    debug_assert_eq!(capacity, plaintext.capacity());
+    Ok(plaintext)
+}
+
+/// Encrypts `plaintext` into locked content of given format.
+/// Salt and key already in `cache` for the same [KdfParams] are reused, fresh ones derived otherwise.
+fn seal(
+    plaintext: &[u8],
+    secret: &[u8],
+    format: LockFormat,
+    cache: &mut KdfCache,
+) -> Result<Vec<u8>, Error> {
+    match format {
+        LockFormat::Legacy => {
+            let (cipher, nonce) = build_cypher_and_random_nonce(secret)?;
+            let mut locked = cipher.encrypt(&nonce, plaintext)?;
+            locked.extend_from_slice(nonce.as_slice());
+            Ok(locked)
+        }
+        LockFormat::V1(kdf) => {
+            let salt = cache.salt_for(kdf).unwrap_or_else(|| {
+                let mut salt = [0u8; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                salt
+            });
+            let header = LockHeader { kdf, salt };
+            let key = cache.key(kdf, salt, secret)?;
+            let (cipher, nonce) = build_cypher_and_random_nonce(key.as_ref())?;
+            let mut locked = header.to_bytes();
+            locked.extend_from_slice(nonce.as_slice());
+            locked.extend_from_slice(&cipher.encrypt(&nonce, plaintext)?);
+            Ok(locked)
+        }
+    }
+}
+
+/// Decrypts locked content of any supported format
+fn open(content: &[u8], secret: &[u8], cache: &mut KdfCache) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let plaintext = match LockHeader::parse(content) {
+        Some((header, rest)) => {
+            let key = cache.key(header.kdf, header.salt, secret)?;
+            let (cipher, _) = build_cypher_and_random_nonce(key.as_ref())?;
+            cipher.decrypt(rest[..NONCE_LEN].into(), &rest[NONCE_LEN..])?
+        }
+        None => {
+            let len = content.len();
+            if len < NONCE_LEN {
+                return Err(Error::MalformedWallet);
+            }
+            let (cipher, _) = build_cypher_and_random_nonce(secret)?;
+            cipher.decrypt(
+                content[len - NONCE_LEN..].into(),
+                &content[..len - NONCE_LEN],
+            )?
+        }
+    };
+    Ok(Zeroizing::new(plaintext))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum KeyType {
+    /// secp256k1 ECDSA signing key.
+    /// Misnamed, but kept as is for compatibility of stored wallets and `Iml`s.
+    #[default]
     Ed25519_256,
+    /// NIST P-256 Diffie-Hellman key agreement key
     EcdhP256,
+    /// Ed25519 signing key
+    Ed25519,
+    /// NIST P-256 ECDSA signing key
+    EcdsaP256,
+    /// X25519 Diffie-Hellman key agreement key
+    X25519,
+}
+
+impl KeyType {
+    /// Checks if keys of this type can sign
+    pub fn is_signing(&self) -> bool {
+        !self.is_agreement()
+    }
+
+    /// Checks if keys of this type are Diffie-Hellman key agreement keys
+    pub fn is_agreement(&self) -> bool {
+        matches!(self, KeyType::EcdhP256 | KeyType::X25519)
+    }
+
+    /// Generates new secret
+    pub(crate) fn generate(&self) -> Zeroizing<[u8; 32]> {
+        let mut sk = Zeroizing::new([0u8; 32]);
+        match self {
+            KeyType::Ed25519_256 => {
+                sk.copy_from_slice(&SigningKey::random(&mut OsRng).to_bytes());
+            }
+            KeyType::EcdhP256 => {
+                let mut seed = Zeroizing::new([0u8; 32]);
+                OsRng.fill_bytes(seed.as_mut());
+                let mut sk_bytes = ECDHNISTP256::generate_private_key(*seed).to_bytes();
+                sk.copy_from_slice(&sk_bytes);
+                sk_bytes.as_mut_slice().zeroize();
+            }
+            // both are clamped on use
+            KeyType::Ed25519 | KeyType::X25519 => OsRng.fill_bytes(sk.as_mut()),
+            KeyType::EcdsaP256 => {
+                sk.copy_from_slice(&p256::ecdsa::SigningKey::random(&mut OsRng).to_bytes());
+            }
+        }
+        sk
+    }
+
+    /// Public key bytes of given secret
+    pub(crate) fn public_from(&self, sk: &[u8; 32]) -> Option<Vec<u8>> {
+        match self {
+            KeyType::Ed25519_256 => SigningKey::from_slice(sk)
+                .ok()
+                .map(|sk| sk.verifying_key().to_sec1_bytes().to_vec()),
+            KeyType::EcdhP256 => SkP256::from_bytes(sk)
+                .ok()
+                .map(|sk| ECDHNISTP256::generate_public_key(&sk).to_bytes().to_vec()),
+            KeyType::Ed25519 => Some(
+                ed25519_dalek::SigningKey::from_bytes(sk)
+                    .verifying_key()
+                    .to_bytes()
+                    .to_vec(),
+            ),
+            KeyType::EcdsaP256 => p256::ecdsa::SigningKey::from_slice(sk)
+                .ok()
+                .map(|sk| sk.verifying_key().to_sec1_bytes().to_vec()),
+            KeyType::X25519 => Some(
+                x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(*sk))
+                    .to_bytes()
+                    .to_vec(),
+            ),
+        }
+    }
+
+    fn sign(&self, sk: &[u8; 32], message: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            KeyType::Ed25519_256 => {
+                let sk = SigningKey::from_slice(sk).map_err(|_| Error::EcdsaFailed)?;
+                Ok(Signer::<Signature>::sign(&sk, message).to_vec())
+            }
+            KeyType::EcdhP256 | KeyType::X25519 => Err(Error::UnsupportedKeyType),
+            KeyType::Ed25519 => Ok(ed25519_dalek::SigningKey::from_bytes(sk)
+                .sign(message)
+                .to_vec()),
+            KeyType::EcdsaP256 => {
+                let sk = p256::ecdsa::SigningKey::from_slice(sk).map_err(|_| Error::EcdsaFailed)?;
+                Ok(Signer::<p256::ecdsa::Signature>::sign(&sk, message).to_vec())
+            }
+        }
+    }
+
+    /// Diffie-Hellman shared secret of `sk` with `their_id` public key
+    pub(crate) fn agree(
+        &self,
+        sk: &[u8; 32],
+        their_id: impl AsRef<[u8]>,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        match self {
+            KeyType::EcdhP256 => {
+                let our_s = SkP256::from_bytes(sk.as_ref())?;
+                let their_pk = PkP256::from_bytes(their_id.as_ref())?;
+                let mut shared =
+                    ECDHNISTP256::generate_shared_secret(&our_s, &their_pk)?.to_bytes();
+                let secret = Zeroizing::new(shared.to_vec());
+                shared.as_mut_slice().zeroize();
+                Ok(secret)
+            }
+            KeyType::X25519 => {
+                let their_pk: [u8; 32] = their_id
+                    .as_ref()
+                    .try_into()
+                    .map_err(|_| Error::ECDHCryptoError)?;
+                let shared = x25519_dalek::StaticSecret::from(*sk)
+                    .diffie_hellman(&x25519_dalek::PublicKey::from(their_pk));
+                // low order point of the peer
+                if !shared.was_contributory() {
+                    return Err(Error::ECDHCryptoError);
+                }
+                Ok(Zeroizing::new(shared.as_bytes().to_vec()))
+            }
+            _ => Err(Error::UnsupportedKeyType),
+        }
+    }
+
+    /// Diffie-Hellman key agreement with fresh ephemeral key of this type,
+    ///  which secret is dropped right after use
+    /// # Parameters
+    /// - `their_id` - public key of other party
+    /// # Returns
+    /// - (ephemeral public key, shared secret)
+    pub fn ephemeral_agreement(
+        &self,
+        their_id: impl AsRef<[u8]>,
+    ) -> Result<(Vec<u8>, Zeroizing<Vec<u8>>), Error> {
+        if !self.is_agreement() {
+            return Err(Error::UnsupportedKeyType);
+        }
+        let sk = self.generate();
+        let public = self.public_from(&sk).ok_or(Error::ECDHCryptoError)?;
+        Ok((public, self.agree(&sk, their_id)?))
+    }
+
+    /// Verifies raw `signature` of `message` against `public` key of this type
+    pub fn verify(&self, public: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        match self {
+            KeyType::Ed25519_256 => VerifyingKey::from_sec1_bytes(public)
+                .ok()
+                .zip(Signature::from_slice(signature).ok())
+                .is_some_and(|(vk, sig)| vk.verify(message, &sig).is_ok()),
+            KeyType::EcdhP256 | KeyType::X25519 => false,
+            KeyType::Ed25519 => <&[u8; 32]>::try_from(public)
+                .ok()
+                .and_then(|pk| ed25519_dalek::VerifyingKey::from_bytes(pk).ok())
+                .zip(ed25519_dalek::Signature::from_slice(signature).ok())
+                .is_some_and(|(vk, sig)| vk.verify(message, &sig).is_ok()),
+            KeyType::EcdsaP256 => p256::ecdsa::VerifyingKey::from_sec1_bytes(public)
+                .ok()
+                .zip(p256::ecdsa::Signature::from_slice(signature).ok())
+                .is_some_and(|(vk, sig)| vk.verify(message, &sig).is_ok()),
+        }
+    }
+}
+
+/// Current unix time in seconds
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
 }
 
 /// Used to identify crypto content through the entire app
@@ -233,27 +1150,76 @@ fn pk_export_import_test() {
     assert_eq!(new_pk, pk_from_bytes);
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Deserialize)]
 struct KeysEntry {
     id: KeyId,
-    sk: [u8; 32],
+    sk: Zeroizing<[u8; 32]>,
+    #[serde(default)]
+    key_type: Option<KeyType>,
+    #[serde(default)]
+    civilization: Option<u64>,
+    #[serde(default)]
+    superseded_at: Option<u64>,
+    #[serde(default)]
+    peer: Option<String>,
 }
 
-impl From<(KeyId, SigningKey)> for KeysEntry {
-    fn from(tuple: (KeyId, SigningKey)) -> Self {
-        Self {
-            id: tuple.0,
-            sk: tuple.1.to_bytes().into(),
-        }
+impl KeysEntry {
+    fn into_key(self) -> (KeyId, WalletKey) {
+        (
+            self.id,
+            WalletKey {
+                sk: self.sk,
+                key_type: self.key_type,
+                civilization: self.civilization,
+                superseded_at: self.superseded_at,
+                peer: self.peer,
+            },
+        )
     }
 }
 
-impl From<(KeyId, [u8; 32])> for KeysEntry {
-    fn from(value: (KeyId, [u8; 32])) -> Self {
-        Self {
-            id: value.0,
-            sk: value.1,
-        }
+/// Borrowing counterpart of [KeysEntry], so secrets are never copied on serialization
+#[derive(Serialize)]
+struct KeysEntryRef<'a> {
+    id: &'a KeyId,
+    sk: &'a [u8; 32],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_type: Option<KeyType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    civilization: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    superseded_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peer: &'a Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RetiredEntry {
+    id: KeyId,
+    key: RetiredKey,
+}
+
+/// Keys list serialization helper
+struct KeysSeq<'a>(&'a HashMap<KeyId, WalletKey>);
+
+impl Serialize for KeysSeq<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        self.0.iter().try_for_each(|(id, key)| {
+            seq.serialize_element(&KeysEntryRef {
+                id,
+                sk: &key.sk,
+                key_type: key.key_type,
+                civilization: key.civilization,
+                superseded_at: key.superseded_at,
+                peer: &key.peer,
+            })
+        })?;
+        seq.end()
     }
 }
 
@@ -262,13 +1228,22 @@ impl Serialize for UnlockedWallet {
     where
         S: serde::Serializer,
     {
-        let mut seq = serializer.serialize_seq(Some(self.keys.len()))?;
-        // FIXME: remove this clone and make sure it's properly zeroized
-        self.keys
-            .clone()
-            .into_iter()
-            .try_for_each(|e| seq.serialize_element(&KeysEntry::from(e)))?;
-        seq.end()
+        let mut map = serializer.serialize_map(Some(4))?;
+        map.serialize_entry("keys", &KeysSeq(&self.keys))?;
+        map.serialize_entry(
+            "retired",
+            &self
+                .retired
+                .iter()
+                .map(|(id, key)| RetiredEntry {
+                    id: *id,
+                    key: key.clone(),
+                })
+                .collect::<Vec<_>>(),
+        )?;
+        map.serialize_entry("retention", &self.retention)?;
+        map.serialize_entry("interaction_grace", &self.interaction_grace.as_secs())?;
+        map.end()
     }
 }
 
@@ -277,12 +1252,64 @@ impl<'de> Deserialize<'de> for UnlockedWallet {
     where
         D: serde::Deserializer<'de>,
     {
-        Ok(Self {
-            keys: Vec::<KeysEntry>::deserialize(deserializer)?
-                .into_iter()
-                .map(|kv| (kv.id, kv.sk))
-                .collect(),
-        })
+        deserializer.deserialize_any(WalletVisitor)
+    }
+}
+
+/// Accepts both plain list of secrets, as stored before key retirement,
+///  and current map layout, without buffering secrets in between.
+struct WalletVisitor;
+
+impl<'de> Visitor<'de> for WalletVisitor {
+    type Value = UnlockedWallet;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("list of wallet keys or wallet content map")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut wallet = UnlockedWallet::new();
+        while let Some(entry) = seq.next_element::<KeysEntry>()? {
+            let (id, key) = entry.into_key();
+            wallet.keys.insert(id, key);
+        }
+        Ok(wallet)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut wallet = UnlockedWallet::new();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "keys" => map
+                    .next_value::<Vec<KeysEntry>>()?
+                    .into_iter()
+                    .for_each(|entry| {
+                        let (id, key) = entry.into_key();
+                        wallet.keys.insert(id, key);
+                    }),
+                "retired" => {
+                    wallet.retired = map
+                        .next_value::<Vec<RetiredEntry>>()?
+                        .into_iter()
+                        .map(|r| (r.id, r.key))
+                        .collect()
+                }
+                "retention" => wallet.retention = map.next_value()?,
+                "interaction_grace" => {
+                    wallet.interaction_grace = Duration::from_secs(map.next_value()?)
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(wallet)
     }
 }
 